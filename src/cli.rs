@@ -7,19 +7,175 @@ pub enum Action {
         /// path to files
         #[clap(short, long)]
         file: std::path::PathBuf,
-        /// if specified, only run this stage
-        #[clap(short, long)]
-        stage: Option<String>,
+        #[command(flatten)]
+        select: Selection,
+        /// wait for another run to release the lock of the host
+        #[clap(long)]
+        wait: bool,
+        /// remove the lock of the host left by another run
+        #[clap(long)]
+        force_unlock: bool,
+        /// skip modules, that succeeded in the last run on the host
+        #[clap(long)]
+        resume: bool,
+        /// skip stages before this one
+        #[clap(long)]
+        start_at_stage: Option<String>,
+        /// ask before every module and every destructive command, like `mkfs`:
+        /// yes, no to leave it out, skip the rest of the stage or abort the run
+        #[clap(long)]
+        step: bool,
+        /// print the changes of every module before it is applied
+        #[clap(long)]
+        diff: bool,
     },
     /// Check installation
     Check {
         /// path to files
         #[clap(short, long)]
         file: std::path::PathBuf,
-        /// if specified, only check this stage
+        #[command(flatten)]
+        select: Selection,
+    },
+    /// Check the playbook without connecting to the hosts
+    Validate {
+        /// path to files
         #[clap(short, long)]
-        stage: Option<String>,
+        file: std::path::PathBuf,
     },
+    /// Print JSON Schema of the playbooks, for autocompletion in editors
+    Schema,
+    /// Convert the playbook between TOML, YAML and JSON
+    Convert {
+        /// playbook to convert
+        #[clap(short, long)]
+        file: std::path::PathBuf,
+        /// converted playbook, its format is taken from the extension.
+        /// Printed to stdout, if not specified
+        #[clap(short, long)]
+        output: Option<std::path::PathBuf>,
+        /// format of the converted playbook, required without `--output`
+        #[clap(long, value_enum)]
+        to: Option<crate::format::Format>,
+    },
+    /// Write a starter playbook with a reference of every module.
+    /// Asks for the hosts and the SSH settings in a terminal, flags are the defaults
+    Init {
+        /// playbook to write
+        #[clap(short, long, default_value = "playbook.toml")]
+        output: std::path::PathBuf,
+        /// hosts of the inventory, `--remote-host` is used without them
+        #[clap(long, value_delimiter = ',')]
+        hosts: Vec<String>,
+        /// do not ask, take the settings from the flags
+        #[clap(short, long)]
+        yes: bool,
+        /// overwrite the playbook, if it exists
+        #[clap(long)]
+        force: bool,
+    },
+    /// Run a shell command on every host at once, i.e. `exec -f hosts.toml -- df -h`
+    Exec {
+        /// playbook with the hosts, the host from the command line is used otherwise
+        #[clap(short, long)]
+        file: Option<std::path::PathBuf>,
+        /// only hosts of the inventory, that match any of these patterns:
+        /// names with `*` and `?` wildcards or names of groups
+        #[clap(short, long, value_delimiter = ',')]
+        limit: Vec<String>,
+        /// run the command with sudo
+        #[clap(long)]
+        sudo: bool,
+        /// print the results as JSON
+        #[clap(long)]
+        json: bool,
+        /// command to run, taken by the remote shell
+        #[clap(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Show runs of the playbooks recorded on the hosts
+    History {
+        /// playbook with the hosts, the host from the command line is used otherwise
+        #[clap(short, long)]
+        file: Option<std::path::PathBuf>,
+        /// number of the latest runs to show
+        #[clap(short = 'n', long, default_value = "10")]
+        last: usize,
+    },
+}
+
+/// stages, modules and hosts the action is limited to
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct Selection {
+    /// only these stages, comma-separated or repeated
+    #[clap(short, long, value_delimiter = ',')]
+    pub stage: Vec<String>,
+    /// stages to skip
+    #[clap(long, value_delimiter = ',')]
+    pub skip_stage: Vec<String>,
+    /// only stages and modules with any of these tags
+    #[clap(short, long, value_delimiter = ',')]
+    pub tags: Vec<String>,
+    /// stages and modules with any of these tags are skipped
+    #[clap(long, value_delimiter = ',')]
+    pub skip_tags: Vec<String>,
+    /// only these modules, i.e. `apt,docker`
+    #[clap(long, value_delimiter = ',')]
+    pub only_module: Vec<String>,
+    /// only hosts of the inventory, that match any of these patterns:
+    /// names with `*` and `?` wildcards or names of groups
+    #[clap(short, long, value_delimiter = ',')]
+    pub limit: Vec<String>,
+}
+
+/// `*` matches any characters, `?` matches a single one
+fn glob(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            glob(&pattern[1..], name) || (!name.is_empty() && glob(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => glob(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) => p == n && glob(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+impl Selection {
+    /// whether every stage and module of the playbook is selected, hosts aside
+    pub fn everything(&self) -> bool {
+        self.stage.is_empty()
+            && self.skip_stage.is_empty()
+            && self.tags.is_empty()
+            && self.skip_tags.is_empty()
+            && self.only_module.is_empty()
+    }
+
+    /// whether the module is selected, given the tags of its stage and its own
+    pub fn module(&self, alias: &str, stage_tags: &[String], tags: &[String]) -> bool {
+        if !self.only_module.is_empty()
+            && !self
+                .only_module
+                .iter()
+                .any(|x| x.replace('_', "-") == alias)
+        {
+            return false;
+        }
+        let mut tags = stage_tags.iter().chain(tags);
+        if !self.tags.is_empty() && !tags.clone().any(|x| self.tags.contains(x)) {
+            return false;
+        }
+        !tags.any(|x| self.skip_tags.contains(x))
+    }
+
+    /// whether the host is selected by `--limit`, by its name or by its groups
+    pub fn host(&self, name: &str, groups: &[String]) -> bool {
+        let name = name.chars().collect::<Vec<_>>();
+        self.limit.is_empty()
+            || self.limit.iter().any(|pattern| {
+                groups.contains(pattern) || glob(&pattern.chars().collect::<Vec<_>>(), &name)
+            })
+    }
 }
 
 // struct for clap CLI args
@@ -41,6 +197,13 @@ pub struct Opts {
     /// path to id_rsa file
     #[clap(long, default_value = "~/.ssh/id_rsa", env = "REMOTE_SSH_KEY_FILE")]
     pub remote_key_file: String,
+    /// combine the probes of every module into a single remote script,
+    /// saves SSH round-trips on distant hosts
+    #[clap(long, env = "REMOTE_BATCH")]
+    pub batch: bool,
+    /// number of commands run at once on a host by `check`
+    #[clap(long, default_value = "8", env = "REMOTE_CHANNELS")]
+    pub channels: usize,
 
     /// Action
     #[command(subcommand)]
@@ -53,27 +216,27 @@ pub struct Opts {
 impl Opts {
     pub fn into_ssh(&self) -> crate::config::Ssh {
         crate::config::Ssh {
-            remote_host: if self.remote_host.len() > 0 {
+            remote_host: if !self.remote_host.is_empty() {
                 Some(self.remote_host.clone())
             } else {
                 None
             },
-            remote_user: if self.remote_user.len() > 0 {
+            remote_user: if !self.remote_user.is_empty() {
                 Some(self.remote_user.clone())
             } else {
                 None
             },
-            remote_password: if self.remote_password.len() > 0 {
+            remote_password: if !self.remote_password.is_empty() {
                 Some(self.remote_password.clone())
             } else {
                 None
             },
             remote_port: if self.remote_port > 0 {
-                Some(self.remote_port.clone())
+                Some(self.remote_port)
             } else {
                 None
             },
-            remote_key_file: if self.remote_key_file.len() > 0 {
+            remote_key_file: if !self.remote_key_file.is_empty() {
                 Some(self.remote_key_file.clone())
             } else {
                 None
@@ -81,3 +244,30 @@ impl Opts {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_selects_modules_and_hosts() {
+        let select = Selection {
+            tags: vec!["web".to_string()],
+            skip_tags: vec!["slow".to_string()],
+            only_module: vec!["apt".to_string(), "node_exporter".to_string()],
+            limit: vec!["web*".to_string(), "db".to_string()],
+            ..Default::default()
+        };
+        let tags = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert!(select.module("apt", &tags(&["web"]), &[]));
+        assert!(select.module("node-exporter", &[], &tags(&["web"])));
+        assert!(!select.module("docker", &tags(&["web"]), &[]));
+        assert!(!select.module("apt", &tags(&["db"]), &[]));
+        assert!(!select.module("apt", &tags(&["web"]), &tags(&["slow"])));
+        assert!(select.host("web1", &[]));
+        assert!(select.host("pg1", &tags(&["db"])));
+        assert!(!select.host("pg1", &tags(&["cache"])));
+        assert!(Selection::default().module("mount", &[], &[]));
+        assert!(Selection::default().host("any", &[]));
+    }
+}
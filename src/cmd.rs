@@ -0,0 +1,158 @@
+//! remote shell commands, where values of the playbook are always quoted,
+//! so a package name or a path can't run anything else on the host
+use std::borrow::Cow;
+
+/// command line to be executed by the remote shell
+#[derive(Debug, Clone, Default)]
+pub struct Cmd(String);
+
+impl Cmd {
+    /// program with its fixed arguments, taken as is
+    pub fn new(program: &str) -> Self {
+        Cmd(program.to_string())
+    }
+
+    /// program that is run with sudo, when it is enabled
+    pub fn sudo(sudo: bool, program: &str) -> Self {
+        match sudo {
+            true => Cmd(format!("sudo {}", program)),
+            false => Cmd::new(program),
+        }
+    }
+
+    fn push(mut self, part: &str) -> Self {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        self.0.push_str(part);
+        self
+    }
+
+    /// argument, quoted for the shell
+    pub fn arg(self, arg: impl AsRef<str>) -> Self {
+        let quoted = quote(arg.as_ref());
+        self.push(&quoted)
+    }
+
+    pub fn args<I, S>(self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        args.into_iter().fold(self, |cmd, arg| cmd.arg(arg))
+    }
+
+    /// remote path, quoted, except for the `~/` or `~user/` prefix
+    /// that should still be expanded by the shell
+    pub fn path(self, path: impl AsRef<str>) -> Self {
+        let quoted = quote_path(path.as_ref());
+        self.push(&quoted)
+    }
+
+    pub fn paths<I, S>(self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        paths.into_iter().fold(self, |cmd, path| cmd.path(path))
+    }
+
+    /// end of the options, the following arguments are taken as operands,
+    /// even if a value of the playbook starts with `-`
+    pub fn operands(self) -> Self {
+        self.push("--")
+    }
+
+    /// shell syntax, taken as is, i.e. `2>&1` or `| grep`
+    pub fn raw(self, part: &str) -> Self {
+        self.push(part)
+    }
+
+    /// the next command is run, when this one succeeded
+    pub fn and(self, next: Cmd) -> Self {
+        self.push("&&").push(&next.0)
+    }
+
+    /// the next command is run after this one in any case
+    pub fn then(mut self, next: Cmd) -> Self {
+        self.0.push(';');
+        self.push(&next.0)
+    }
+}
+
+impl std::ops::Deref for Cmd {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Cmd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn is_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-_./=:,+@%^".contains(c)
+}
+
+/// single argument for the shell, quoted when it has any special characters
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        return Cow::Borrowed(arg);
+    }
+    Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+}
+
+/// remote path for the shell, `~` and `~user` prefixes are kept unquoted
+pub fn quote_path(path: &str) -> Cow<'_, str> {
+    let Some(rest) = path.strip_prefix('~') else {
+        return quote(path);
+    };
+    let (user, relative) = match rest.split_once('/') {
+        Some((user, relative)) => (user, Some(relative)),
+        None => (rest, None),
+    };
+    if !user
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+    {
+        return quote(path);
+    }
+    match relative {
+        None => Cow::Borrowed(path),
+        Some("") => Cow::Owned(format!("~{}/", user)),
+        Some(relative) => Cow::Owned(format!("~{}/{}", user, quote(relative))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_quotes_arguments_and_paths() {
+        assert_eq!(quote("nginx"), "nginx");
+        assert_eq!(quote("a b"), "'a b'");
+        assert_eq!(quote("x; rm -rf /"), "'x; rm -rf /'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote_path("~/my dir/f"), "~/'my dir/f'");
+        assert_eq!(quote_path("~deploy/.bashrc"), "~deploy/.bashrc");
+        assert_eq!(quote_path("~"), "~");
+        assert_eq!(quote_path("~$(id)/x"), "'~$(id)/x'");
+
+        let cmd = Cmd::sudo(true, "apt-get install -yq")
+            .args(["curl", "$(reboot)"])
+            .raw("2>&1");
+        assert_eq!(&*cmd, "sudo apt-get install -yq curl '$(reboot)' 2>&1");
+        let cmd = Cmd::new("mkdir -p")
+            .path("/data/a b")
+            .and(Cmd::new("ls").path("~/x"));
+        assert_eq!(cmd.to_string(), "mkdir -p '/data/a b' && ls ~/x");
+        let cmd = Cmd::new("mkdir -p").operands().path("-rf");
+        assert_eq!(cmd.to_string(), "mkdir -p -- -rf");
+    }
+}
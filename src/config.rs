@@ -1,17 +1,186 @@
-use crate::remote::Stage;
+use crate::format::{Format, EXTENSIONS};
+use crate::interpolate::{interpolate, unescape};
+use crate::remote::{OnError, ProfileOptions, Stage};
 
-use serde::Deserialize;
+use anyhow::Context;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer};
 use std::collections::BTreeMap as Map;
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Deserialize)]
+/// variables of the playbook, available in templates
+pub type Vars = Map<String, serde_json::Value>;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub ssh: Option<Ssh>,
+    /// inventory: hosts the playbook is applied to, by name.
+    /// If not declared, the single host from `[ssh]` is used
+    pub hosts: Option<Map<String, Host>>,
+    /// groups of the hosts with their variables
+    #[serde(default)]
+    pub groups: Map<String, Group>,
+    #[serde(default)]
+    pub vars: Vars,
+    /// what happens when a module fails, unless its stage or the module declares it
+    pub on_error: Option<OnError>,
+    /// playbooks merged under this one, paths are relative to the playbook
+    #[serde(default)]
+    pub include: Vec<PathBuf>,
+    /// roles from the `roles` folder next to the playbook, applied before `[stages]`
+    #[serde(default)]
+    pub roles: Vec<RoleUse>,
+    #[serde(default)]
     pub stages: Map<String, Stage>,
-    pub aliases: Option<Map<String, String>>,
-    pub exports: Option<Map<String, String>>,
+    /// top-level `[aliases]`, applied as a stage of its own after `[stages]`
+    #[serde(default, deserialize_with = "aliases_stage")]
+    #[schemars(with = "Option<ProfileOptions>")]
+    pub aliases: Option<Stage>,
+    /// top-level `[exports]`, applied as a stage of its own after `[stages]`
+    #[serde(default, deserialize_with = "exports_stage")]
+    #[schemars(with = "Option<ProfileOptions>")]
+    pub exports: Option<Stage>,
+    /// playbook as it was read, with `${name}` references not resolved yet
+    #[serde(skip)]
+    raw: serde_json::Value,
+    /// roles as they were read, in the order of `roles`
+    #[serde(skip)]
+    role_docs: Vec<Role>,
+    /// stages of the roles, resolved for the host
+    #[serde(skip)]
+    role_stages: Vec<(String, Stage)>,
+}
+
+/// use of the role in the playbook, i.e. `roles = [{ role = "site", name = "api", vars = { port = 8080 } }]`
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct RoleUse {
+    /// folder of the role in `roles`
+    pub role: String,
+    /// prefix of the stages of this use, defaults to the role.
+    /// The same role can be used twice with different names
+    pub name: Option<String>,
+    /// parameters of this use, override variables of the host and defaults of the role
+    #[serde(default)]
+    pub vars: Vars,
+}
+
+impl RoleUse {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.role)
+    }
 }
 
+/// role is a folder with `role.toml` (or `.yaml`, `.yml`, `.json`), which declares default `[vars]` and `[stages]`,
+/// and with the files of the role, that are referred to as `${role_dir}/files/...`
+#[derive(Debug)]
+struct Role {
+    dir: PathBuf,
+    /// `role.toml` or the file of the role in another format
+    file: PathBuf,
+    /// default variables
+    vars: Vars,
+    /// stages with `${name}` references not resolved yet
+    stages: serde_json::Value,
+}
+
+/// file of a role, as it is declared. Only parsed to be validated
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+#[allow(dead_code)]
+struct RoleFile {
+    #[serde(default)]
+    vars: Vars,
+    #[serde(default)]
+    stages: Map<String, Stage>,
+}
+
+/// strict parsing of the file and of the files it includes,
+/// `stack` holds the files that include this one
+fn check(path: &Path, stack: &mut Vec<PathBuf>) -> anyhow::Result<Config> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("read {}", path.display()))?;
+    if stack.contains(&canonical) {
+        anyhow::bail!("{} includes itself", path.display());
+    }
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let cfg: Config = Format::from_path(path)
+        .deserialize(&contents)
+        .with_context(|| format!("{}", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    stack.push(canonical);
+    for file in &cfg.include {
+        check(&dir.join(file), stack)?;
+    }
+    stack.pop();
+    Ok(cfg)
+}
+
+/// table of the playbook file, merged over the files it includes
+fn read(path: &Path, stack: &mut Vec<PathBuf>) -> anyhow::Result<serde_json::Value> {
+    let canonical = path
+        .canonicalize()
+        .with_context(|| format!("read {}", path.display()))?;
+    if stack.contains(&canonical) {
+        anyhow::bail!("{} includes itself", path.display());
+    }
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    let mut doc = Format::from_path(path)
+        .parse(&contents)
+        .with_context(|| format!("parse {}", path.display()))?;
+    let include = match doc.as_object_mut().and_then(|x| x.remove("include")) {
+        Some(include) => Vec::<PathBuf>::deserialize(include)
+            .with_context(|| format!("include of {}", path.display()))?,
+        None => vec![],
+    };
+    let dir = path.parent().unwrap_or(Path::new(""));
+    stack.push(canonical);
+    let mut merged = serde_json::Value::Object(Default::default());
+    for file in include {
+        merge(&mut merged, read(&dir.join(file), stack)?);
+    }
+    stack.pop();
+    merge(&mut merged, doc);
+    Ok(merged)
+}
+
+/// tables are merged key by key, other values of `over` replace the values of `base`
+fn merge(base: &mut serde_json::Value, over: serde_json::Value) {
+    match (base, over) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(over)) => {
+            for (key, value) in over {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, over) => *base = over,
+    }
+}
+
+fn aliases_stage<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Stage>, D::Error> {
+    Ok(Some(Stage {
+        aliases: Some(ProfileOptions::deserialize(deserializer)?.into()),
+        ..Default::default()
+    }))
+}
+
+fn exports_stage<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Stage>, D::Error> {
+    Ok(Some(Stage {
+        exports: Some(ProfileOptions::deserialize(deserializer)?.into()),
+        ..Default::default()
+    }))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Ssh {
     pub remote_host: Option<String>,
     pub remote_user: Option<String>,
@@ -20,6 +189,307 @@ pub struct Ssh {
     pub remote_key_file: Option<String>,
 }
 
+impl Ssh {
+    /// settings, where missing values are taken from the fallback
+    pub fn or(&self, fallback: &Ssh) -> Ssh {
+        Ssh {
+            remote_host: self.remote_host.clone().or(fallback.remote_host.clone()),
+            remote_user: self.remote_user.clone().or(fallback.remote_user.clone()),
+            remote_port: self.remote_port.or(fallback.remote_port),
+            remote_password: self
+                .remote_password
+                .clone()
+                .or(fallback.remote_password.clone()),
+            remote_key_file: self
+                .remote_key_file
+                .clone()
+                .or(fallback.remote_key_file.clone()),
+        }
+    }
+}
+
+/// host of the inventory, connection settings override `[ssh]`
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Host {
+    /// address of the host, defaults to the name of the host
+    pub remote_host: Option<String>,
+    pub remote_user: Option<String>,
+    pub remote_port: Option<u16>,
+    pub remote_password: Option<String>,
+    pub remote_key_file: Option<String>,
+    /// variables of this host, override playbook `[vars]`
+    #[serde(default)]
+    pub vars: Vars,
+}
+
+/// group of the inventory, variables override playbook `[vars]`
+/// and are overridden by variables of the host
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Group {
+    pub hosts: Vec<String>,
+    #[serde(default)]
+    pub vars: Vars,
+}
+
+/// sections, that are resolved when the playbook is read:
+/// connection settings take only `${env:NAME}`, variables are not expanded in variables
+const SETTINGS: [&str; 4] = ["ssh", "hosts", "groups", "vars"];
+
+/// host to be processed, with connection settings and variables resolved
+#[derive(Debug)]
+pub struct Target {
+    pub name: String,
+    pub ssh: Ssh,
+    pub vars: Vars,
+    /// groups of the inventory, the host belongs to
+    pub groups: Vec<String>,
+}
+
+impl Config {
+    /// read the playbook from the file, with its includes and roles
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let raw = read(path, &mut vec![])?;
+        let mut cfg = Self::from_raw(raw).with_context(|| format!("parse {}", path.display()))?;
+        let roles_dir = path.parent().unwrap_or(Path::new("")).join("roles");
+        let mut names = std::collections::BTreeSet::new();
+        for role in &cfg.roles {
+            if !names.insert(role.name()) {
+                anyhow::bail!(
+                    "role {} is used twice, give the uses different names",
+                    role.name()
+                );
+            }
+            let dir = roles_dir.join(&role.role);
+            let file = EXTENSIONS
+                .iter()
+                .map(|ext| dir.join(format!("role.{}", ext)))
+                .find(|file| file.exists())
+                .with_context(|| format!("role {} not found in {}", role.role, dir.display()))?;
+            let mut doc =
+                read(&file, &mut vec![]).with_context(|| format!("role {}", role.role))?;
+            interpolate(&mut doc, None, &role.role)?;
+            let mut vars = doc.get("vars").cloned().unwrap_or_default();
+            unescape(&mut vars);
+            cfg.role_docs.push(Role {
+                dir,
+                file,
+                vars: match vars {
+                    serde_json::Value::Null => Vars::new(),
+                    vars => Vars::deserialize(vars)?,
+                },
+                stages: doc.get("stages").cloned().unwrap_or_default(),
+            });
+        }
+        Ok(cfg)
+    }
+
+    /// checksum of the playbook as it is applied: the file merged with its includes,
+    /// and the roles it uses, so a change in any of them changes the checksum
+    pub fn sha256(&self) -> String {
+        let roles = self
+            .role_docs
+            .iter()
+            .map(|role| serde_json::json!({ "vars": role.vars, "stages": role.stages }))
+            .collect::<Vec<_>>();
+        let doc = serde_json::json!({ "playbook": self.raw, "roles": roles });
+        crate::prelude::sha256_hex(doc.to_string().as_bytes())
+    }
+
+    /// check the playbook without connecting to the hosts: unknown fields and modules,
+    /// wrong types, `when` expressions, includes, roles and variables of every host
+    pub fn validate(path: &Path) -> anyhow::Result<Config> {
+        check(path, &mut vec![])?;
+        let cfg = Self::load(path)?;
+        for (role, use_) in cfg.role_docs.iter().zip(&cfg.roles) {
+            let contents = std::fs::read_to_string(&role.file)?;
+            Format::from_path(&role.file)
+                .deserialize::<RoleFile>(&contents)
+                .with_context(|| format!("role {}: {}", use_.name(), role.file.display()))?;
+        }
+        let hosts = cfg.hosts.as_ref();
+        for (name, group) in &cfg.groups {
+            for host in &group.hosts {
+                if !hosts.is_some_and(|x| x.contains_key(host)) {
+                    anyhow::bail!("host {} of the group {} is not declared", host, name);
+                }
+            }
+        }
+        for target in cfg.targets(&Ssh::default()) {
+            cfg.resolve(&target)
+                .with_context(|| format!("host {}", target.name))?;
+        }
+        Ok(cfg)
+    }
+
+    /// JSON Schema of the playbooks
+    pub fn schema() -> schemars::schema::RootSchema {
+        let mut schema = schemars::schema_for!(Config);
+        // aliases of the modules are accepted as well
+        if let Some(schemars::schema::Schema::Object(stage)) = schema.definitions.get_mut("Stage") {
+            if let Some(object) = stage.object.as_mut() {
+                for (alias, name) in [
+                    ("node-exporter", "node_exporter"),
+                    ("docker-stats", "docker_stats"),
+                ] {
+                    if let Some(property) = object.properties.get(name).cloned() {
+                        object.properties.insert(alias.to_string(), property);
+                    }
+                }
+            }
+        }
+        schema
+    }
+
+    /// playbook with `${env:NAME}` resolved, `${name}` is resolved per host by `resolve`
+    pub fn parse(contents: &str) -> anyhow::Result<Config> {
+        Self::from_raw(toml::from_str(contents)?)
+    }
+
+    fn from_raw(mut raw: serde_json::Value) -> anyhow::Result<Config> {
+        let doc = raw.as_object_mut().context("playbook is not a table")?;
+        let no_vars = Vars::new();
+        for (key, value) in doc.iter_mut() {
+            match key.as_str() {
+                "ssh" => interpolate(value, Some(&no_vars), key)?,
+                "hosts" | "groups" => {
+                    let entries = value.as_object_mut().into_iter().flatten();
+                    for (name, entry) in entries {
+                        let settings = entry.as_object_mut().into_iter().flatten();
+                        for (setting, value) in settings {
+                            let path = format!("{}.{}.{}", key, name, setting);
+                            let vars = (setting != "vars").then_some(&no_vars);
+                            interpolate(value, vars, &path)?;
+                            if setting == "vars" {
+                                unescape(value);
+                            }
+                        }
+                    }
+                }
+                "vars" => {
+                    interpolate(value, None, key)?;
+                    unescape(value);
+                }
+                _ => interpolate(value, None, key)?,
+            }
+        }
+        let mut cfg = Config::deserialize(raw.clone())?;
+        cfg.raw = raw;
+        Ok(cfg)
+    }
+
+    /// playbook for the host: `${name}` references are replaced
+    /// with the variables of the host, `${host}` is its name
+    pub fn resolve(&self, target: &Target) -> anyhow::Result<Config> {
+        let mut vars = target.vars.clone();
+        vars.entry("host".to_string())
+            .or_insert_with(|| target.name.clone().into());
+        let mut raw = self.raw.clone();
+        let doc = raw.as_object_mut().context("playbook is not a table")?;
+        for (key, value) in doc.iter_mut() {
+            if !SETTINGS.contains(&key.as_str()) {
+                interpolate(value, Some(&vars), key)?;
+            }
+        }
+        let mut cfg = Config::deserialize(raw)?;
+        let mut role_stages = vec![];
+        for (role, use_) in self.role_docs.iter().zip(&cfg.roles) {
+            let mut vars = role.vars.clone();
+            vars.extend(target.vars.clone());
+            vars.extend(use_.vars.clone());
+            vars.insert("host".to_string(), target.name.clone().into());
+            vars.insert(
+                "role_dir".to_string(),
+                role.dir.display().to_string().into(),
+            );
+            let mut stages = role.stages.clone();
+            interpolate(&mut stages, Some(&vars), use_.name())?;
+            let stages = match stages {
+                serde_json::Value::Null => Map::new(),
+                stages => Map::<String, Stage>::deserialize(stages)
+                    .with_context(|| format!("role {}", use_.name()))?,
+            };
+            for (name, stage) in stages {
+                role_stages.push((format!("{}.{}", use_.name(), name), stage));
+            }
+        }
+        cfg.role_stages = role_stages;
+        Ok(cfg)
+    }
+
+    /// stages in the order they are applied: stages of the roles
+    /// in the order of `roles`, `[stages]`, top-level sections are the last
+    pub fn stages(&self) -> Vec<(&str, &Stage)> {
+        let mut stages = self
+            .role_stages
+            .iter()
+            .map(|(name, stage)| (name, stage))
+            .chain(&self.stages)
+            .map(|(name, stage)| (name.as_str(), stage))
+            .collect::<Vec<_>>();
+        if let Some(stage) = &self.aliases {
+            stages.push(("aliases", stage));
+        }
+        if let Some(stage) = &self.exports {
+            stages.push(("exports", stage));
+        }
+        stages
+    }
+
+    /// stage by its name, including top-level `aliases` and `exports`
+    pub fn stage(&self, name: &str) -> Option<&Stage> {
+        self.stages()
+            .into_iter()
+            .find(|(x, _)| *x == name)
+            .map(|(_, stage)| stage)
+    }
+
+    /// hosts to be processed, connection settings are resolved
+    /// in the order: host of the inventory, `[ssh]` section, command line
+    pub fn targets(&self, args: &Ssh) -> Vec<Target> {
+        let defaults = match &self.ssh {
+            Some(ssh) => ssh.or(args),
+            None => args.clone(),
+        };
+        let Some(hosts) = &self.hosts else {
+            return vec![Target {
+                name: defaults.remote_host.clone().unwrap_or_default(),
+                ssh: defaults,
+                vars: self.vars.clone(),
+                groups: vec![],
+            }];
+        };
+        hosts
+            .iter()
+            .map(|(name, host)| {
+                let ssh = Ssh {
+                    remote_host: Some(host.remote_host.clone().unwrap_or(name.clone())),
+                    remote_user: host.remote_user.clone(),
+                    remote_port: host.remote_port,
+                    remote_password: host.remote_password.clone(),
+                    remote_key_file: host.remote_key_file.clone(),
+                };
+                let mut vars = self.vars.clone();
+                let mut groups = vec![];
+                for (group_name, group) in &self.groups {
+                    if group.hosts.contains(name) {
+                        vars.extend(group.vars.clone());
+                        groups.push(group_name.clone());
+                    }
+                }
+                vars.extend(host.vars.clone());
+                Target {
+                    name: name.clone(),
+                    ssh: ssh.or(&defaults),
+                    vars,
+                    groups,
+                }
+            })
+            .collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,7 +511,188 @@ docker = { path = "/data" }
 terraform = {}
     "#;
 
-        let config: Config = toml::from_str(&contents).unwrap();
+        let config: Config = toml::from_str(contents).unwrap();
         println!("{:?}", config);
     }
+
+    #[test]
+    fn test_profile_stages() {
+        let contents = r#"
+[aliases]
+ll = "ls -alF"
+
+[exports]
+target = "profile.d"
+items = { EDITOR = "vim" }
+
+[stages.shell]
+aliases = { k = "kubectl" }
+"#;
+        let config: Config = toml::from_str(contents).unwrap();
+        let names = config
+            .stages()
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["shell", "aliases", "exports"]);
+        let exports = config.stage("exports").unwrap().exports.as_ref().unwrap();
+        assert_eq!(exports.items["EDITOR"], "vim");
+        assert!(config.stage("shell").unwrap().aliases.is_some());
+    }
+
+    #[test]
+    fn test_targets() {
+        let contents = r#"
+[ssh]
+remote_user = "azureuser"
+
+[vars]
+domain = "example.com"
+workers = 2
+
+[hosts.web1]
+remote_host = "10.0.0.1"
+vars = { workers = 4 }
+
+[hosts.web2]
+
+[stages]
+"#;
+        let config: Config = toml::from_str(contents).unwrap();
+        let args = Ssh {
+            remote_user: Some("root".to_string()),
+            remote_port: Some(22),
+            ..Default::default()
+        };
+        let targets = config.targets(&args);
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].name, "web1");
+        assert_eq!(targets[0].ssh.remote_host.as_deref(), Some("10.0.0.1"));
+        assert_eq!(targets[0].ssh.remote_user.as_deref(), Some("azureuser"));
+        assert_eq!(targets[0].ssh.remote_port, Some(22));
+        assert_eq!(targets[0].vars["workers"], 4);
+        assert_eq!(targets[0].vars["domain"], "example.com");
+        assert_eq!(targets[1].ssh.remote_host.as_deref(), Some("web2"));
+        assert_eq!(targets[1].vars["workers"], 2);
+    }
+
+    #[test]
+    fn test_resolve() {
+        std::env::set_var("REMOTE_PLAYBOOK_USER", "deploy");
+        let contents = r#"
+[ssh]
+remote_user = "${env:REMOTE_PLAYBOOK_USER}"
+
+[vars]
+data = "/data"
+
+[groups.db]
+hosts = ["db1"]
+vars = { data = "/pgdata" }
+
+[hosts.web1]
+[hosts.db1]
+
+[stages.disk]
+mount = { to = "${data}" }
+mkdir = { sudo = true, folders = ["${data}/logs", "/backup/${host}"] }
+"#;
+        let config = Config::parse(contents).unwrap();
+        assert_eq!(
+            config.ssh.as_ref().unwrap().remote_user.as_deref(),
+            Some("deploy")
+        );
+        let targets = config.targets(&Ssh::default());
+        let db1 = config.resolve(&targets[0]).unwrap();
+        assert_eq!(db1.stages["disk"].mount.as_ref().unwrap().to, "/pgdata");
+        let web1 = config.resolve(&targets[1]).unwrap();
+        let mkdir = web1.stages["disk"].mkdir.as_ref().unwrap();
+        assert_eq!(mkdir.folders, vec!["/data/logs", "/backup/web1"]);
+
+        let config = Config::parse("[stages.disk]\nmount = { to = \"${missing}\" }").unwrap();
+        let err = config.resolve(&targets[0]).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "in stages.disk.mount.to: undefined variable missing"
+        );
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema = serde_json::to_value(Config::schema()).unwrap();
+        let stage = &schema["definitions"]["Stage"]["properties"];
+        assert_eq!(stage["mkdir"]["additionalProperties"], false);
+        assert!(stage["mkdir"]["properties"]["on_error"].is_object());
+        assert!(stage["node-exporter"].is_object());
+        let aliases = serde_json::to_string(&stage["aliases"]).unwrap();
+        assert!(aliases.contains("\"anyOf\""));
+    }
+
+    #[test]
+    fn test_includes_and_roles() {
+        let dir = std::env::temp_dir().join(format!("remote-playbook-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("common")).unwrap();
+        std::fs::create_dir_all(dir.join("roles/site")).unwrap();
+        let files = [
+            (
+                "common/base.toml",
+                "[vars]\ndata = \"/data\"\n[stages.essentials]\napt = { install = [\"curl\"] }\n",
+            ),
+            (
+                "roles/site/role.toml",
+                "[vars]\nport = 80\n[stages.nginx]\ncopy = { src = \"${role_dir}/files/site.conf\", dest = \"/etc/nginx/conf.d/${name}.conf\" }\nmkdir = { sudo = true, folders = [\"${data}/${name}-${port}\"] }\n",
+            ),
+            (
+                "playbook.toml",
+                "include = [\"common/base.toml\"]\nroles = [{ role = \"site\", vars = { name = \"www\" } }, { role = \"site\", name = \"api\", vars = { name = \"api\", port = 8080 } }]\n[stages.essentials]\napt = { install = [\"jq\"] }\n",
+            ),
+        ];
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        let config = Config::load(&dir.join("playbook.toml")).unwrap();
+        let targets = config.targets(&Ssh::default());
+        let config = config.resolve(&targets[0]).unwrap();
+        let names = config
+            .stages()
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["site.nginx", "api.nginx", "essentials"]);
+        let apt = config.stage("essentials").unwrap().apt.as_ref().unwrap();
+        assert_eq!(apt.install, vec!["jq"]);
+        let api = config.stage("api.nginx").unwrap();
+        assert_eq!(api.mkdir.as_ref().unwrap().folders, vec!["/data/api-8080"]);
+        let site = config.stage("site.nginx").unwrap();
+        assert_eq!(site.mkdir.as_ref().unwrap().folders, vec!["/data/www-80"]);
+        assert!(site.copy.as_ref().unwrap()[0]
+            .src
+            .ends_with("roles/site/files/site.conf"));
+
+        // the checksum covers the includes and the roles, not just the playbook file
+        let sha256 = Config::load(&dir.join("playbook.toml")).unwrap().sha256();
+        std::fs::write(dir.join("common/base.toml"), "[vars]\ndata = \"/srv\"\n").unwrap();
+        let included = Config::load(&dir.join("playbook.toml")).unwrap().sha256();
+        assert_ne!(sha256, included);
+        std::fs::write(dir.join("roles/site/role.toml"), "[vars]\nport = 81\n").unwrap();
+        let role = Config::load(&dir.join("playbook.toml")).unwrap().sha256();
+        assert_ne!(included, role);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir =
+            std::env::temp_dir().join(format!("remote-playbook-cycle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.toml"), "include = [\"b.toml\"]\n").unwrap();
+        std::fs::write(dir.join("b.toml"), "include = [\"a.toml\"]\n").unwrap();
+        let err = Config::validate(&dir.join("a.toml")).unwrap_err();
+        assert!(
+            err.to_string().ends_with("a.toml includes itself"),
+            "{}",
+            err
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
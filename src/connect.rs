@@ -1,21 +1,20 @@
-use crate::config::{Config, Ssh};
+use crate::config::Ssh;
 use anyhow::Context;
 use async_ssh2_tokio::client::{AuthMethod, Client, ServerCheckMethod};
 use std::path::Path;
 
-pub fn tilde_with_context<SI: ?Sized, P, HD>(input: &SI, home_dir: HD) -> String
+pub fn tilde_with_context<SI, P, HD>(input: &SI, home_dir: HD) -> String
 where
-    SI: AsRef<str>,
+    SI: AsRef<str> + ?Sized,
     P: AsRef<Path>,
     HD: FnOnce() -> Option<P>,
 {
     let input_str = input.as_ref();
-    if input_str.starts_with("~") {
-        let input_after_tilde = &input_str[1..];
+    if let Some(input_after_tilde) = input_str.strip_prefix("~") {
         if input_after_tilde.is_empty() || input_after_tilde.starts_with("/") {
             if let Some(hd) = home_dir() {
                 let result = format!("{}{}", hd.as_ref().display(), input_after_tilde);
-                result.into()
+                result
             } else {
                 // home dir is not available
                 input_str.into()
@@ -30,31 +29,17 @@ where
     }
 }
 
-// get ssh client, combine args and config, config has higher priority
-pub async fn get_client(args: Ssh, cfg: &Config) -> anyhow::Result<Client> {
+// get ssh client for the settings of the target host
+pub async fn get_client(ssh: &Ssh) -> anyhow::Result<Client> {
     let method = {
-        let password = match &cfg.ssh {
-            Some(ssh) => match &ssh.remote_password {
-                Some(password) => password.to_string(),
-                None => args.remote_password.unwrap_or("".to_string()),
-            },
-            None => "".to_string(),
-        };
-
-        if password.len() > 0 {
+        let password = ssh.remote_password.clone().unwrap_or_default();
+        if !password.is_empty() {
             AuthMethod::with_password(&password)
         } else {
-            let raw_path_key = match &cfg.ssh {
-                Some(ssh) => match &ssh.remote_key_file {
-                    Some(file) => file.to_string(),
-                    None => args
-                        .remote_key_file
-                        .context("no private key file provided")?,
-                },
-                None => args
-                    .remote_key_file
-                    .context("no private key file provided")?,
-            };
+            let raw_path_key = ssh
+                .remote_key_file
+                .clone()
+                .context("no private key file provided")?;
             let path_key = tilde_with_context(&raw_path_key, dirs::home_dir);
             let private_key = std::fs::read_to_string(&path_key)
                 .context(format!("invalid private key {}", path_key))?;
@@ -62,30 +47,11 @@ pub async fn get_client(args: Ssh, cfg: &Config) -> anyhow::Result<Client> {
         }
     };
 
-    let host = match &cfg.ssh {
-        Some(ssh) => match &ssh.remote_host {
-            Some(host) => host.to_string(),
-            None => args.remote_host.unwrap_or("".to_string()),
-        },
-        None => args.remote_host.unwrap_or("".to_string()),
-    };
-    let port = match &cfg.ssh {
-        Some(ssh) => match &ssh.remote_port {
-            Some(port) => *port,
-            None => args.remote_port.unwrap_or(22),
-        },
-        None => args.remote_port.unwrap_or(22),
-    };
-    let username = match &cfg.ssh {
-        Some(ssh) => match &ssh.remote_user {
-            Some(host) => host.to_string(),
-            None => args.remote_user.unwrap_or("".to_string()),
-        },
-        None => args.remote_user.unwrap_or("".to_string()),
-    };
-    Ok(
-        Client::connect((host, port), &username, method, ServerCheckMethod::NoCheck)
-            .await
-            .unwrap(),
-    )
+    let host = ssh.remote_host.clone().unwrap_or_default();
+    let port = ssh.remote_port.unwrap_or(22);
+    let username = ssh.remote_user.clone().unwrap_or_default();
+    let address = format!("{}@{}:{}", username, host, port);
+    Client::connect((host, port), &username, method, ServerCheckMethod::NoCheck)
+        .await
+        .context(format!("failed to connect to {}", address))
 }
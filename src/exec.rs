@@ -0,0 +1,90 @@
+//! ad-hoc command run by `exec` on every selected host at once
+use crate::cmd::Cmd;
+use crate::config::Target;
+use color_eyre::owo_colors::OwoColorize;
+use serde::Serialize;
+
+/// result of the command on a host
+#[derive(Debug, Serialize)]
+pub struct HostOutput {
+    pub host: String,
+    /// exit status of the command, missing if the host was not reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_status: Option<u32>,
+    /// stdout and stderr of the command
+    pub output: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HostOutput {
+    pub fn ok(&self) -> bool {
+        self.exit_status == Some(0)
+    }
+
+    pub fn print(&self) {
+        println!("@ {}", self.host.cyan());
+        for line in self.output.lines() {
+            println!("  {}", line);
+        }
+        match (&self.error, self.exit_status) {
+            (Some(e), _) => println!("- {}: {} {}", self.host.red(), "FAILURE".red(), e),
+            (None, Some(0)) => println!("+ {}: {}", self.host.green(), "OK".green()),
+            (None, status) => println!(
+                "- {}: {} exit {}",
+                self.host.red(),
+                "FAILURE".red(),
+                status.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// shell command line of `exec`, stderr is joined with stdout
+pub fn command(command: &str, sudo: bool) -> String {
+    Cmd::sudo(sudo, "sh -c")
+        .arg(command)
+        .raw("2>&1")
+        .to_string()
+}
+
+async fn on_host(target: &Target, command: &str) -> anyhow::Result<(u32, String)> {
+    let client = crate::connect::get_client(&target.ssh).await?;
+    let out = crate::prelude::silent(&client, command).await?;
+    Ok((out.exit_status, out.output))
+}
+
+/// run the command on the hosts concurrently, results are in the order of the hosts
+pub async fn run(targets: &[Target], command: &str) -> Vec<HostOutput> {
+    let runs = targets.iter().map(|target| async move {
+        match on_host(target, command).await {
+            Ok((exit_status, output)) => HostOutput {
+                host: target.name.clone(),
+                exit_status: Some(exit_status),
+                output,
+                error: None,
+            },
+            Err(e) => HostOutput {
+                host: target.name.clone(),
+                exit_status: None,
+                output: String::new(),
+                error: Some(format!("{:#}", e)),
+            },
+        }
+    });
+    futures::future::join_all(runs).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_quotes_the_command() {
+        assert_eq!(command("df -h", false), "sh -c 'df -h' 2>&1");
+        assert_eq!(
+            command("echo 'a' > /x", true),
+            r#"sudo sh -c 'echo '\''a'\'' > /x' 2>&1"#
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! `when` expressions of stages and modules, i.e. `facts.distro == 'ubuntu' && facts.arch == 'x86_64'`.
+//! Names are paths into the scope: `facts`, `vars`, `results` and `host`.
+//! Operators are `==`, `!=`, `<`, `<=`, `>`, `>=`, `in`, `&&`, `||`, `!` and parentheses
+use crate::prelude::*;
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Str(String),
+    Num(f64),
+    Name(String),
+    Op(&'static str),
+    Open,
+    Close,
+}
+
+/// names, that paths of the expression start with
+const ROOTS: [&str; 4] = ["facts", "vars", "results", "host"];
+
+const OPS: [&str; 9] = ["==", "!=", "<=", ">=", "&&", "||", "<", ">", "!"];
+
+fn tokenize(src: &str) -> anyhow::Result<Vec<Token>> {
+    let chars = src.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|x| *x == c)
+                .context(format!("unterminated string at {}", i))?;
+            tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+            i += end + 2;
+        } else if c.is_ascii_digit() {
+            let len = chars[i..]
+                .iter()
+                .take_while(|x| x.is_ascii_digit() || **x == '.')
+                .count();
+            let number = chars[i..i + len].iter().collect::<String>();
+            // dotted numbers are kept as written, so `22.10` stays a version newer than `22.1`
+            if number.contains('.') {
+                version(&number).context(format!("invalid number {}", number))?;
+                tokens.push(Token::Str(number));
+            } else {
+                tokens.push(Token::Num(
+                    number
+                        .parse()
+                        .context(format!("invalid number {}", number))?,
+                ));
+            }
+            i += len;
+        } else if c.is_alphabetic() || c == '_' {
+            let len = chars[i..]
+                .iter()
+                .take_while(|x| x.is_alphanumeric() || "_.-".contains(**x))
+                .count();
+            let name = chars[i..i + len].iter().collect::<String>();
+            tokens.push(match name.as_str() {
+                "in" => Token::Op("in"),
+                _ => Token::Name(name),
+            });
+            i += len;
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(*op))
+                .context(format!("unexpected {:?} at {}", c, i))?;
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Value(Value),
+    Path(Vec<String>),
+    Not(Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// binary operators of the level, that are left-associative
+    fn binary(
+        &mut self,
+        ops: &[&str],
+        operand: fn(&mut Self) -> anyhow::Result<Node>,
+    ) -> anyhow::Result<Node> {
+        let mut left = operand(self)?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            if !ops.contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            left = Node::Binary(op, Box::new(left), Box::new(operand(self)?));
+        }
+        Ok(left)
+    }
+
+    fn or(&mut self) -> anyhow::Result<Node> {
+        self.binary(&["||"], Self::and)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Node> {
+        self.binary(&["&&"], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> anyhow::Result<Node> {
+        self.binary(&["==", "!=", "<=", ">=", "<", ">", "in"], Self::unary)
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Node> {
+        match self.next() {
+            Some(Token::Op("!")) => Ok(Node::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let node = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(node),
+                    _ => bail!("expected )"),
+                }
+            }
+            Some(Token::Str(s)) => Ok(Node::Value(Value::String(s))),
+            Some(Token::Num(n)) => Ok(Node::Value(n.into())),
+            Some(Token::Name(name)) => Ok(match name.as_str() {
+                "true" => Node::Value(Value::Bool(true)),
+                "false" => Node::Value(Value::Bool(false)),
+                "null" => Node::Value(Value::Null),
+                _ => {
+                    let path = name.split('.').map(String::from).collect::<Vec<_>>();
+                    if !ROOTS.contains(&path[0].as_str()) {
+                        bail!(
+                            "unknown name {}, expected one of: {}",
+                            path[0],
+                            ROOTS.join(", ")
+                        );
+                    }
+                    Node::Path(path)
+                }
+            }),
+            Some(token) => bail!("unexpected {:?}", token),
+            None => bail!("unexpected end of the expression"),
+        }
+    }
+}
+
+/// parsed `when` expression
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    src: String,
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(src: &str) -> anyhow::Result<Expr> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+        };
+        let root = parser.or()?;
+        if let Some(token) = parser.peek() {
+            bail!("unexpected {:?}", token);
+        }
+        Ok(Expr {
+            src: src.to_string(),
+            root,
+        })
+    }
+
+    /// the expression as it was declared
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    /// value of the expression in the scope, which is a table of the names
+    pub fn eval(&self, scope: &Value) -> anyhow::Result<Value> {
+        eval(&self.root, scope).context(format!("when {}", self.src))
+    }
+
+    /// whether the expression holds in the scope
+    pub fn test(&self, scope: &Value) -> anyhow::Result<bool> {
+        Ok(truthy(&self.eval(scope)?))
+    }
+}
+
+impl<'de> Deserialize<'de> for Expr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let src = String::deserialize(deserializer)?;
+        Expr::parse(&src).map_err(|e| serde::de::Error::custom(format!("when {:?}: {}", src, e)))
+    }
+}
+
+impl JsonSchema for Expr {
+    fn schema_name() -> String {
+        "Expr".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        String::json_schema(gen)
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+/// number of the value, strings like `0.5` are compared as numbers too
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// components of a version like `22.04` or `1.2.3`, a whole number has a single one
+fn version(s: &str) -> Option<Vec<u64>> {
+    s.split('.').map(|x| x.parse().ok()).collect()
+}
+
+/// version of the value, numbers with a fraction are not versions
+fn version_of(value: &Value) -> Option<Vec<u64>> {
+    match value {
+        Value::Number(n) => Some(vec![n.as_u64()?]),
+        Value::String(s) => version(s),
+        _ => None,
+    }
+}
+
+/// versions compared component by component as integers, missing ones are zeros:
+/// `22.10 > 22.4` and `1.0 == 1`
+fn compare_versions(a: &[u64], b: &[u64]) -> std::cmp::Ordering {
+    (0..a.len().max(b.len()))
+        .map(|i| {
+            let x = a.get(i).unwrap_or(&0);
+            let y = b.get(i).unwrap_or(&0);
+            x.cmp(y)
+        })
+        .find(|x| x.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+fn equal(left: &Value, right: &Value) -> bool {
+    if let (Some(a), Some(b)) = (version_of(left), version_of(right)) {
+        return compare_versions(&a, &b).is_eq();
+    }
+    match (left, right) {
+        (Value::Number(_), _) | (_, Value::Number(_)) => {
+            matches!((number(left), number(right)), (Some(a), Some(b)) if a == b)
+        }
+        _ => left == right,
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> anyhow::Result<std::cmp::Ordering> {
+    if let (Some(a), Some(b)) = (version_of(left), version_of(right)) {
+        return Ok(compare_versions(&a, &b));
+    }
+    if let (Value::String(a), Value::String(b)) = (left, right) {
+        return Ok(a.cmp(b));
+    }
+    match (number(left), number(right)) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).context("not comparable"),
+        _ => bail!("can't compare {} with {}", left, right),
+    }
+}
+
+fn eval(node: &Node, scope: &Value) -> anyhow::Result<Value> {
+    match node {
+        Node::Value(value) => Ok(value.clone()),
+        Node::Path(path) => Ok(path
+            .iter()
+            .try_fold(scope, |value, key| value.get(key))
+            .cloned()
+            .unwrap_or(Value::Null)),
+        Node::Not(node) => Ok(Value::Bool(!truthy(&eval(node, scope)?))),
+        Node::Binary("&&", left, right) => Ok(Value::Bool(
+            truthy(&eval(left, scope)?) && truthy(&eval(right, scope)?),
+        )),
+        Node::Binary("||", left, right) => Ok(Value::Bool(
+            truthy(&eval(left, scope)?) || truthy(&eval(right, scope)?),
+        )),
+        Node::Binary(op, left, right) => {
+            let (left, right) = (eval(left, scope)?, eval(right, scope)?);
+            let result = match *op {
+                "==" => equal(&left, &right),
+                "!=" => !equal(&left, &right),
+                "<" => compare(&left, &right)?.is_lt(),
+                "<=" => compare(&left, &right)?.is_le(),
+                ">" => compare(&left, &right)?.is_gt(),
+                ">=" => compare(&left, &right)?.is_ge(),
+                "in" => match &right {
+                    Value::Array(items) => items.iter().any(|x| equal(x, &left)),
+                    Value::String(s) => s.contains(left.as_str().unwrap_or_default()),
+                    Value::Object(map) => map.contains_key(left.as_str().unwrap_or_default()),
+                    Value::Null => false,
+                    _ => bail!("{} is not a list, a string or a table", right),
+                },
+                _ => unreachable!(),
+            };
+            Ok(Value::Bool(result))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_evaluates_expressions() {
+        let scope = json!({
+            "facts": { "distro": "ubuntu", "arch": "x86_64", "distro_version": "22.04", "cpus": 4 },
+            "vars": { "roles": ["web", "db"], "docker": true },
+            "results": { "mount": "ok" },
+            "host": "web1",
+        });
+        let test = |src: &str| Expr::parse(src).unwrap().test(&scope).unwrap();
+        assert!(test("facts.distro == 'ubuntu' && facts.arch == \"x86_64\""));
+        assert!(!test("facts.distro == 'debian' || facts.cpus < 2"));
+        assert!(test("facts.distro_version >= 20.04 && facts.cpus == 4"));
+        assert!(test("'web' in vars.roles && !('cache' in vars.roles)"));
+        assert!(test(
+            "vars.docker && results.mount == 'ok' && host != 'web2'"
+        ));
+        assert!(!test("vars.missing"));
+        assert!(Expr::parse("fact.distro == 'ubuntu'").is_err());
+        assert!(Expr::parse("facts.cpus > vars.roles")
+            .unwrap()
+            .test(&scope)
+            .is_err());
+        assert!(Expr::parse("facts.distro == ").is_err());
+        assert!(Expr::parse("(facts.distro == 'a'").is_err());
+        assert!(Expr::parse("facts.distro = 'a'").is_err());
+    }
+
+    #[test]
+    fn it_compares_versions_by_components() {
+        let scope = json!({
+            "facts": { "distro_version": "22.10", "kernel": "6.8.0", "cpus": 4, "load": 0.5 },
+        });
+        let test = |src: &str| Expr::parse(src).unwrap().test(&scope).unwrap();
+        assert!(test("facts.distro_version > 22.4"));
+        assert!(test("facts.distro_version != 22.1"));
+        assert!(!test("facts.distro_version == 22.1"));
+        assert!(test(
+            "facts.distro_version == 22.10 && facts.distro_version < 23.04"
+        ));
+        assert!(test("facts.kernel >= 6.8 && facts.kernel < 6.10.1"));
+        assert!(test("facts.cpus == 4.0 && facts.cpus > 2"));
+        assert!(test("facts.load < 1 && facts.load > 0.25"));
+        assert!(Expr::parse("facts.kernel > 6..8").is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! facts of the host, gathered once before the stages are applied,
+//! so `when` expressions can tell hosts of a mixed fleet apart
+use crate::config::Vars;
+use crate::prelude::*;
+use serde_json::Value;
+
+/// single script, so the facts take one round-trip
+const GATHER: &str = "uname -s; uname -m; uname -r; hostname; nproc; \
+    awk '/^MemTotal:/ { print int($2 / 1024) }' /proc/meminfo; cat /etc/os-release 2>/dev/null";
+
+/// facts from the output of the gathering script:
+/// `os`, `arch`, `kernel`, `hostname`, `cpus`, `memory_mb`,
+/// `distro`, `distro_version`, `distro_codename` and `distro_like`
+fn parse(output: &str) -> Vars {
+    let mut lines = output.lines();
+    let mut facts = Vars::new();
+    for name in ["os", "arch", "kernel", "hostname"] {
+        let value = lines.next().unwrap_or_default().trim();
+        let value = match name {
+            "os" => value.to_lowercase(),
+            _ => value.to_string(),
+        };
+        facts.insert(name.to_string(), Value::String(value));
+    }
+    for name in ["cpus", "memory_mb"] {
+        let value = lines.next().unwrap_or_default().trim();
+        let value = value.parse::<u64>().map(Value::from).unwrap_or(Value::Null);
+        facts.insert(name.to_string(), value);
+    }
+    let os_release = lines
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches('"')))
+        .collect::<Map<_, _>>();
+    let fields = [
+        ("distro", "ID"),
+        ("distro_version", "VERSION_ID"),
+        ("distro_codename", "VERSION_CODENAME"),
+        ("distro_like", "ID_LIKE"),
+    ];
+    for (name, key) in fields {
+        let value = os_release.get(key).copied().unwrap_or_default();
+        facts.insert(name.to_string(), Value::String(value.to_string()));
+    }
+    facts
+}
+
+#[instrument(skip(client))]
+pub async fn gather(client: &Client) -> anyhow::Result<Vars> {
+    let out = silent(client, GATHER).await?;
+    Ok(parse(&out.output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_facts() {
+        let output = "Linux\nx86_64\n5.15.0-91-generic\nweb1\n4\n7951\n\
+            NAME=\"Ubuntu\"\nVERSION_ID=\"22.04\"\nID=ubuntu\nID_LIKE=debian\nVERSION_CODENAME=jammy\n";
+        let facts = parse(output);
+        assert_eq!(facts["os"], "linux");
+        assert_eq!(facts["arch"], "x86_64");
+        assert_eq!(facts["cpus"], 4);
+        assert_eq!(facts["memory_mb"], 7951);
+        assert_eq!(facts["distro"], "ubuntu");
+        assert_eq!(facts["distro_version"], "22.04");
+        assert_eq!(facts["distro_codename"], "jammy");
+        assert_eq!(facts["distro_like"], "debian");
+    }
+}
@@ -0,0 +1,81 @@
+//! formats of the playbook files, told apart by the extension:
+//! `.yaml`/`.yml` and `.json` share the schema of TOML playbooks
+use crate::prelude::*;
+use serde_json::Value;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+/// extensions of the playbook files, in the order they are looked up
+pub const EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+
+impl Format {
+    /// format of the file by its extension, TOML if it has any other
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("yaml" | "yml") => Format::Yaml,
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+
+    pub fn parse(self, contents: &str) -> anyhow::Result<Value> {
+        Ok(match self {
+            Format::Toml => toml::from_str(contents)?,
+            Format::Yaml => serde_yaml::from_str(contents)?,
+            Format::Json => serde_json::from_str(contents)?,
+        })
+    }
+
+    /// strict parsing into the type, errors point at the line and column
+    pub fn deserialize<T: serde::de::DeserializeOwned>(self, contents: &str) -> anyhow::Result<T> {
+        Ok(match self {
+            Format::Toml => toml::from_str(contents)?,
+            Format::Yaml => serde_yaml::from_str(contents)?,
+            Format::Json => serde_json::from_str(contents)?,
+        })
+    }
+
+    pub fn render(self, value: &Value) -> anyhow::Result<String> {
+        Ok(match self {
+            Format::Toml => toml::to_string_pretty(value).context("TOML has no null values")?,
+            Format::Yaml => serde_yaml::to_string(value)?,
+            Format::Json => serde_json::to_string_pretty(value)? + "\n",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_between_formats() {
+        let toml = r#"
+[ssh]
+remote_user = "azureuser"
+
+[stages.disk]
+mount = { to = "/data" }
+mkdir = { sudo = true, folders = ["/data/logs"] }
+"#;
+        let value = Format::Toml.parse(toml).unwrap();
+        let yaml = Format::Yaml.render(&value).unwrap();
+        assert!(yaml.contains("stages:\n  disk:\n    mount:\n      to: /data\n"));
+        let json = Format::Json
+            .render(&Format::Yaml.parse(&yaml).unwrap())
+            .unwrap();
+        let back = Format::Toml
+            .render(&Format::Json.parse(&json).unwrap())
+            .unwrap();
+        assert_eq!(Format::Toml.parse(&back).unwrap(), value);
+        assert_eq!(Format::from_path(Path::new("site.yml")), Format::Yaml);
+        assert_eq!(Format::from_path(Path::new("site.json")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("site")), Format::Toml);
+    }
+}
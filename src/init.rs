@@ -0,0 +1,188 @@
+//! starter playbook written by `init`. The reference of the modules at its end
+//! is generated from the schema of the options, so it follows the code
+use crate::config::Config;
+use crate::remote::MODULES;
+use serde_json::Value;
+use std::io::{BufRead, IsTerminal, Write};
+
+/// connection settings and hosts of the starter playbook
+#[derive(Debug, Default)]
+pub struct Starter {
+    pub remote_host: String,
+    pub remote_user: String,
+    pub remote_key_file: String,
+    /// hosts of the inventory, `remote_host` is used without them
+    pub hosts: Vec<String>,
+}
+
+impl Starter {
+    /// ask for the settings in the terminal, given values are the defaults
+    pub fn prompt(mut self) -> anyhow::Result<Self> {
+        if !std::io::stdin().is_terminal() {
+            return Ok(self);
+        }
+        let ask = |question: &str, default: &str| -> anyhow::Result<String> {
+            print!("{} [{}]: ", question, default);
+            std::io::stdout().flush()?;
+            let mut answer = String::new();
+            std::io::stdin().lock().read_line(&mut answer)?;
+            Ok(match answer.trim() {
+                "" => default.to_string(),
+                answer => answer.to_string(),
+            })
+        };
+        let hosts = ask(
+            "hosts of the inventory, comma-separated",
+            &self.hosts.join(","),
+        )?;
+        self.hosts = hosts
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
+        if self.hosts.is_empty() {
+            self.remote_host = ask("remote host", &self.remote_host)?;
+        }
+        self.remote_user = ask("remote user", &self.remote_user)?;
+        self.remote_key_file = ask("key file", &self.remote_key_file)?;
+        Ok(self)
+    }
+
+    /// the starter playbook in TOML
+    pub fn playbook(&self) -> String {
+        let mut out = String::from(
+            "# playbook of remote-playbook, check it with `remote-playbook validate --file <FILE>`\n\n",
+        );
+        out.push_str("[ssh]\n");
+        if self.hosts.is_empty() {
+            out.push_str(&format!("remote_host = {:?}\n", self.remote_host));
+        }
+        out.push_str(&format!("remote_user = {:?}\n", self.remote_user));
+        out.push_str(&format!("remote_key_file = {:?}\n\n", self.remote_key_file));
+        for host in &self.hosts {
+            out.push_str(&format!("[hosts.{}]\n\n", key(host)));
+        }
+        out.push_str(
+            r#"[vars]
+data = "/data"
+
+[stages.essentials]
+mkdir = { sudo = true, folders = ["${data}"] }
+apt = { install = ["ca-certificates", "curl", "gnupg", "jq", "vim"] }
+
+[stages.docker]
+docker = { path = "${data}" }
+
+"#,
+        );
+        out.push_str(&reference());
+        out
+    }
+}
+
+/// key of a TOML table, quoted if it is not a bare key
+fn key(name: &str) -> String {
+    let bare = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if !name.is_empty() && name.chars().all(bare) {
+        name.to_string()
+    } else {
+        format!("{:?}", name)
+    }
+}
+
+/// table of the options in the schema of the module
+fn table(schema: &Value) -> Option<&Value> {
+    if schema.get("properties").is_some() {
+        return Some(schema);
+    }
+    schema.get("anyOf")?.as_array()?.iter().find_map(table)
+}
+
+/// value of the option in the example of the module
+fn placeholder(schema: &Value) -> &'static str {
+    let kind = match &schema["type"] {
+        Value::Array(types) => types.first().and_then(|x| x.as_str()),
+        kind => kind.as_str(),
+    };
+    match kind {
+        Some("array") => "[]",
+        Some("boolean") => "true",
+        Some("object") => "{}",
+        Some("integer" | "number") => "0",
+        _ => "\"\"",
+    }
+}
+
+/// commented options of every module, with an example of the required ones
+pub fn reference() -> String {
+    let schema = serde_json::to_value(Config::schema()).unwrap_or_default();
+    let stage = &schema["definitions"]["Stage"]["properties"];
+    let mut out = String::from(
+        "# Modules of a stage, every module also takes `on_error`, `when` and `tags`\n#\n",
+    );
+    for module in MODULES {
+        let Some(options) = table(&stage[module]) else {
+            continue;
+        };
+        let description = options["description"].as_str().unwrap_or_default();
+        out.push_str(&format!(
+            "# {}: {}\n",
+            module,
+            description.replace('\n', " ")
+        ));
+        let required = options["required"].as_array().cloned().unwrap_or_default();
+        let mut example = vec![];
+        for (name, option) in options["properties"].as_object().into_iter().flatten() {
+            if ["on_error", "when", "tags"].contains(&name.as_str()) {
+                continue;
+            }
+            let is_required = required.iter().any(|x| x == name);
+            let description = option["description"].as_str().unwrap_or_default();
+            let mark = if is_required { " (required)" } else { "" };
+            out.push_str(&format!(
+                "#   {}{}: {}\n",
+                name,
+                mark,
+                description.replace('\n', " ")
+            ));
+            if is_required {
+                example.push(format!("{} = {}", name, placeholder(option)));
+            }
+        }
+        match example.is_empty() {
+            true => out.push_str(&format!("# {} = {{}}\n#\n", module)),
+            false => out.push_str(&format!("# {} = {{ {} }}\n#\n", module, example.join(", "))),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_writes_a_valid_playbook() {
+        let starter = Starter {
+            remote_user: "root".to_string(),
+            remote_key_file: "~/.ssh/id_rsa".to_string(),
+            hosts: vec!["web1".to_string(), "db.internal".to_string()],
+            ..Default::default()
+        };
+        let playbook = starter.playbook();
+        let config = Config::parse(&playbook).unwrap();
+        assert_eq!(config.hosts.unwrap().len(), 2);
+        assert!(config.stages.contains_key("docker"));
+        for module in MODULES {
+            assert!(
+                playbook.contains(&format!("\n# {}: ", module)),
+                "{}",
+                module
+            );
+        }
+        assert!(playbook.contains("[hosts.web1]\n"));
+        assert!(playbook.contains("[hosts.\"db.internal\"]\n"));
+        assert!(playbook.contains("# mkdir = { folders = [], sudo = true }\n"));
+        assert!(playbook.contains("# terraform = {}\n"));
+    }
+}
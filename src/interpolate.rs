@@ -0,0 +1,154 @@
+//! `${name}` and `${env:NAME}` references in the string values of the playbook.
+//! `${name}` is a variable of the host, dotted names go into tables, i.e. `${db.host}`.
+//! A string that is a single reference takes the value with its type, `$${` is a literal `${`
+use crate::config::Vars;
+use crate::prelude::*;
+use serde_json::Value;
+
+fn lookup<'a>(vars: &'a Vars, name: &str) -> anyhow::Result<&'a Value> {
+    let mut parts = name.split('.');
+    let first = parts.next().unwrap_or_default();
+    vars.get(first)
+        .and_then(|root| parts.try_fold(root, |value, key| value.get(key)))
+        .context(format!("undefined variable {}", name))
+}
+
+fn env(name: &str) -> anyhow::Result<String> {
+    std::env::var(name).context(format!("environment variable {} is not set", name))
+}
+
+/// value of the string, `vars` are not resolved yet when they are not given
+fn expand(s: &str, vars: Option<&Vars>) -> anyhow::Result<Value> {
+    if let (Some(vars), Some(name)) = (vars, s.strip_prefix("${").and_then(|x| x.strip_suffix('}')))
+    {
+        if !name.starts_with("env:") && !name.contains(['$', '{', '}']) {
+            return lookup(vars, name).cloned();
+        }
+    }
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if rest.starts_with("$${") {
+            out.push_str(if vars.is_some() { "${" } else { "$${" });
+            rest = &rest[3..];
+            continue;
+        }
+        if !rest.starts_with("${") {
+            out.push('$');
+            rest = &rest[1..];
+            continue;
+        }
+        let end = rest.find('}').context(format!("unterminated {}", rest))?;
+        let name = &rest[2..end];
+        match (name.strip_prefix("env:"), vars) {
+            // without `vars` the string is expanded again, so the value is escaped
+            (Some(name), None) => out.push_str(&env(name)?.replace("${", "$${")),
+            (Some(name), Some(_)) => out.push_str(&env(name)?),
+            (None, None) => out.push_str(&rest[..=end]),
+            (None, Some(vars)) => match lookup(vars, name)? {
+                Value::String(value) => out.push_str(value),
+                value @ (Value::Number(_) | Value::Bool(_)) => out.push_str(&value.to_string()),
+                _ => bail!("variable {} is not a string or a number", name),
+            },
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(Value::String(out))
+}
+
+/// resolve references in every string of the value, `path` is used in errors.
+/// Without `vars` only `${env:NAME}` is resolved, the rest is kept as is
+pub fn interpolate(value: &mut Value, vars: Option<&Vars>, path: &str) -> anyhow::Result<()> {
+    match value {
+        Value::String(s) => *value = expand(s, vars).context(format!("in {}", path))?,
+        Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                interpolate(item, vars, &format!("{}[{}]", path, i))?;
+            }
+        }
+        Value::Object(map) => {
+            for (key, item) in map.iter_mut() {
+                interpolate(item, vars, &format!("{}.{}", path, key))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `$${` to `${` in every string of the value, for the values that are only
+/// expanded without `vars`, i.e. the variables themselves
+pub fn unescape(value: &mut Value) {
+    match value {
+        Value::String(s) => *s = s.replace("$${", "${"),
+        Value::Array(items) => items.iter_mut().for_each(unescape),
+        Value::Object(map) => map.values_mut().for_each(unescape),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn it_expands_references() {
+        std::env::set_var("REMOTE_PLAYBOOK_TEST", "secret");
+        let vars = serde_json::from_value::<Vars>(json!({
+            "data": "/data",
+            "port": 8080,
+            "dirs": ["/data/a", "/data/b"],
+            "db": { "host": "db1" },
+        }))
+        .unwrap();
+        let mut value = json!({
+            "to": "${data}",
+            "path": "${data}/docker",
+            "url": "http://${db.host}:${port}/?key=${env:REMOTE_PLAYBOOK_TEST}",
+            "port": "${port}",
+            "folders": "${dirs}",
+            "cost": "$5, $${data}",
+        });
+        interpolate(&mut value, Some(&vars), "stages.x").unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "to": "/data",
+                "path": "/data/docker",
+                "url": "http://db1:8080/?key=secret",
+                "port": 8080,
+                "folders": ["/data/a", "/data/b"],
+                "cost": "$5, ${data}",
+            })
+        );
+
+        let mut value = json!({ "a": ["${env:REMOTE_PLAYBOOK_TEST}-${data}", "$${x}"] });
+        interpolate(&mut value, None, "vars").unwrap();
+        assert_eq!(value, json!({ "a": ["secret-${data}", "$${x}"] }));
+
+        let mut value = json!({ "mount": { "to": "${missing}/x" } });
+        let err = interpolate(&mut value, Some(&vars), "stages.disk").unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "in stages.disk.mount.to: undefined variable missing"
+        );
+        assert!(interpolate(&mut json!("${env:REMOTE_PLAYBOOK_MISSING}"), None, "ssh").is_err());
+
+        // values of the environment are taken literally in both passes
+        std::env::set_var("REMOTE_PLAYBOOK_TEST_REF", "p${data}");
+        let mut value = json!({ "password": "${env:REMOTE_PLAYBOOK_TEST_REF}", "cost": "$${x}" });
+        interpolate(&mut value, None, "stages.x").unwrap();
+        let mut vars_section = value.clone();
+        interpolate(&mut value, Some(&vars), "stages.x").unwrap();
+        assert_eq!(value, json!({ "password": "p${data}", "cost": "${x}" }));
+        unescape(&mut vars_section);
+        assert_eq!(
+            vars_section,
+            json!({ "password": "p${data}", "cost": "${x}" })
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! local journal of the last `install` on every host, written after each stage,
+//! so an interrupted or failed run can be resumed with `--resume`
+use crate::prelude::*;
+use crate::remote::StageReport;
+use std::path::PathBuf;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Journal {
+    pub playbook_sha256: String,
+    pub stages: Vec<StageReport>,
+}
+
+/// `~/.local/share/remote-playbook/journal/<host>.json` on Linux
+fn path(host: &str) -> anyhow::Result<PathBuf> {
+    let name = host
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || "-_.".contains(c) {
+            true => c,
+            false => '_',
+        })
+        .collect::<String>();
+    let dir = dirs::data_local_dir().context("no local data directory")?;
+    Ok(dir
+        .join("remote-playbook")
+        .join("journal")
+        .join(format!("{}.json", name)))
+}
+
+impl Journal {
+    pub fn new(playbook_sha256: &str) -> Self {
+        Journal {
+            playbook_sha256: playbook_sha256.to_string(),
+            stages: vec![],
+        }
+    }
+
+    /// journal of the last run on the host, if there was any
+    pub fn load(host: &str) -> anyhow::Result<Option<Journal>> {
+        let path = path(host)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read(&path).context(format!("read {}", path.display()))?;
+        let journal = serde_json::from_slice(&contents)
+            .with_context(|| format!("parse {}", path.display()))?;
+        Ok(Some(journal))
+    }
+
+    pub fn save(&self, host: &str) -> anyhow::Result<()> {
+        let path = path(host)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)
+            .context(format!("write {}", path.display()))
+    }
+
+    /// update the journal with the report of the stage. Modules that were not run
+    /// this time, i.e. with `--stage`, `--start-at-stage` or `--resume`, keep their results
+    pub fn merge(&mut self, report: StageReport) {
+        let Some(stage) = self.stages.iter_mut().find(|x| x.name == report.name) else {
+            self.stages.push(report);
+            return;
+        };
+        for module in report.modules {
+            match stage.modules.iter_mut().find(|x| x.module == module.module) {
+                Some(existing) => *existing = module,
+                None => stage.modules.push(module),
+            }
+        }
+        stage.stopped = report.stopped;
+    }
+
+    /// modules of the stage, that succeeded in the runs of the journal
+    pub fn done(&self, stage: &str) -> Vec<String> {
+        self.stages
+            .iter()
+            .filter(|x| x.name == stage)
+            .flat_map(|x| &x.modules)
+            .filter(|module| module.error.is_none())
+            .map(|module| module.module.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{ModuleReport, OnError};
+
+    fn report(name: &str, modules: &[(&str, bool)]) -> StageReport {
+        StageReport {
+            name: name.to_string(),
+            modules: modules
+                .iter()
+                .map(|(module, ok)| ModuleReport {
+                    module: module.to_string(),
+                    error: (!ok).then(|| "failed".to_string()),
+                })
+                .collect(),
+            stopped: None,
+        }
+    }
+
+    #[test]
+    fn it_keeps_results_of_earlier_runs() {
+        let mut journal = Journal::new("abc");
+        journal.merge(report("essentials", &[("apt", true), ("mkdir", true)]));
+        let mut docker = report("docker", &[("docker", false)]);
+        docker.stopped = Some(OnError::SkipStage);
+        journal.merge(docker);
+        // `--stage docker` after the fix
+        journal.merge(report("docker", &[("docker", true)]));
+        assert_eq!(journal.done("essentials"), vec!["apt", "mkdir"]);
+        assert_eq!(journal.done("docker"), vec!["docker"]);
+        assert_eq!(journal.stages[1].stopped, None);
+    }
+}
@@ -0,0 +1,92 @@
+//! lock of the host for the time of `install`, so two runs of the playbook
+//! can't interleave their changes. The lock file is created with `noclobber`,
+//! which fails if it already exists, and holds who took the lock and when
+use crate::prelude::*;
+use std::time::Duration;
+
+const LOCK_PATH: &str = "/var/lock/remote-playbook.lock";
+const WAIT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// who runs the playbook: `user@host`
+pub fn operator() -> String {
+    format!(
+        "{}@{}",
+        whoami::username(),
+        whoami::fallible::hostname().unwrap_or_default()
+    )
+}
+
+/// operator with the process, that holds the lock: `user@host (pid N)`
+fn holder() -> String {
+    format!("{} (pid {})", operator(), std::process::id())
+}
+
+/// try to take the lock once, returns the current holder if it is taken.
+/// Creating the file fails for other reasons as well (no sudo, read-only `/var/lock`),
+/// it is contention only when the lock file exists
+async fn try_acquire(client: &Client, holder: &str) -> anyhow::Result<Option<String>> {
+    let script = format!(
+        "set -C; echo {} since $(date -u +%FT%TZ) > {}",
+        quote(holder),
+        LOCK_PATH
+    );
+    let cmd = Cmd::new("sudo sh -c").arg(script).raw("2>&1");
+    let out = silent(client, &cmd).await?;
+    if out.exit_status == 0 {
+        return Ok(None);
+    }
+    let script = format!(
+        "if [ -e {} ]; then cat {}; else exit 3; fi",
+        LOCK_PATH, LOCK_PATH
+    );
+    let current = silent(client, &Cmd::new("sudo sh -c").arg(script).raw("2>&1")).await?;
+    match current.exit_status {
+        0 => Ok(Some(current.output.trim().to_string())),
+        _ => bail!(
+            "cannot create {}: {} (exit {})",
+            LOCK_PATH,
+            out.output.trim(),
+            out.exit_status
+        ),
+    }
+}
+
+/// take the lock of the host, waiting for it to be released if `wait` is set.
+/// `force` removes the lock left by another run, i.e. one that was interrupted
+#[instrument(skip(client))]
+pub async fn acquire(client: &Client, wait: bool, force: bool) -> anyhow::Result<()> {
+    if force {
+        warn!("removing the lock {}", LOCK_PATH);
+        run(client, &Cmd::new("sudo rm -f").path(LOCK_PATH)).await?;
+    }
+    let holder = holder();
+    let mut waiting = false;
+    while let Some(current) = try_acquire(client, &holder).await? {
+        if !wait {
+            bail!(
+                "host is locked by {}, use --wait or --force-unlock",
+                current
+            );
+        }
+        if !waiting {
+            println!("~ waiting for the lock held by {}", current);
+            waiting = true;
+        }
+        tokio::time::sleep(WAIT_INTERVAL).await;
+    }
+    debug!("lock {} acquired by {}", LOCK_PATH, holder);
+    Ok(())
+}
+
+/// release the lock, if it is still held by this run
+#[instrument(skip(client))]
+pub async fn release(client: &Client) -> anyhow::Result<()> {
+    let script = format!(
+        "grep -qF -- {} {} && rm -f {}",
+        quote(&holder()),
+        LOCK_PATH,
+        LOCK_PATH
+    );
+    silent(client, &Cmd::new("sudo sh -c").arg(script)).await?;
+    Ok(())
+}
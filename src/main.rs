@@ -1,11 +1,24 @@
 pub mod cli;
+pub mod cmd;
 pub mod config;
 pub mod connect;
+pub mod exec;
+pub mod expr;
+pub mod facts;
+pub mod format;
+pub mod init;
+pub mod interpolate;
+pub mod journal;
+pub mod lock;
 pub mod logging;
 pub mod prelude;
 pub mod remote;
+pub mod state;
 
+use anyhow::Context;
+use async_ssh2_tokio::client::Client;
 use clap::Parser;
+use color_eyre::owo_colors::OwoColorize;
 use tracing::*;
 
 #[tokio::main]
@@ -17,87 +30,441 @@ pub async fn main() -> anyhow::Result<()> {
     let args = cli::Opts::parse();
     debug!("{:?}", args);
     let ssh = args.into_ssh();
+    let probing = prelude::Probing {
+        batch: args.batch,
+        channels: args.channels,
+    };
+    let started = std::time::Instant::now();
 
     match args.action {
-        cli::Action::Install { file, stage } => {
-            // read toml config from file
-            let cfg: config::Config =
-                toml::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
-            let client = connect::get_client(ssh, &cfg).await.unwrap();
-            match stage {
-                Some(stage) => {
-                    if stage == "aliases" {
-                        if let Some(items) = &cfg.aliases {
-                            remote::alias::install(&client, items).await.unwrap();
-                        } else {
-                            panic!("no aliases declared");
-                        }
-                    } else if stage == "exports" {
-                        if let Some(items) = &cfg.exports {
-                            remote::export::install(&client, items).await.unwrap();
-                        } else {
-                            panic!("no exports declared");
-                        }
-                    } else {
-                        remote::install(&client, &stage, &cfg.stages[&stage])
-                            .await
-                            .unwrap();
-                        if let Some(aliases) = &cfg.aliases {
-                            remote::alias::install(&client, aliases).await.unwrap();
+        cli::Action::Install {
+            file,
+            select,
+            wait,
+            force_unlock,
+            resume,
+            start_at_stage,
+            step,
+            diff,
+        } => {
+            let playbook = config::Config::load(&file)?;
+            let playbook_sha256 = playbook.sha256();
+            let mut failed = vec![];
+            for (target, cfg) in resolved(&playbook, &ssh, &select)? {
+                let mut stages = selected(&cfg, &select)?;
+                if let Some(start) = &start_at_stage {
+                    let index = stages
+                        .iter()
+                        .position(|(name, _)| name == start)
+                        .with_context(|| format!("stage {} is not selected", start))?;
+                    stages.drain(..index);
+                }
+                if cfg.hosts.is_some() {
+                    println!("@ {}", target.name.cyan());
+                }
+                // the journal goes on while the playbook stays the same
+                let last = journal::Journal::load(&target.name)?;
+                let changed = last
+                    .as_ref()
+                    .is_some_and(|x| x.playbook_sha256 != playbook_sha256);
+                if resume && changed {
+                    println!(
+                        "- {}: {} playbook has changed since the last run, run it without --resume",
+                        target.name.red(),
+                        "FAILURE".red()
+                    );
+                    failed.push(target.name.clone());
+                    continue;
+                }
+                let previous = last.clone().filter(|_| resume);
+                let mut journal = match last {
+                    Some(last) if !changed => last,
+                    _ => journal::Journal::new(&playbook_sha256),
+                };
+                let client = match connect::get_client(&target.ssh).await {
+                    Ok(client) => client,
+                    Err(e) => {
+                        println!("- {}: {} {:#}", target.name.red(), "FAILURE".red(), e);
+                        failed.push(target.name.clone());
+                        continue;
+                    }
+                };
+                let facts = match facts::gather(&client).await {
+                    Ok(facts) => facts,
+                    Err(e) => {
+                        println!(
+                            "- {}: {} facts: {:#}",
+                            target.name.red(),
+                            "FAILURE".red(),
+                            e
+                        );
+                        failed.push(target.name.clone());
+                        continue;
+                    }
+                };
+                let ctx = remote::Context {
+                    on_error: cfg.on_error,
+                    facts,
+                    selection: select.clone(),
+                    step,
+                    diff,
+                    probing,
+                    ..remote::Context::new(&target)
+                };
+                if let Err(e) = lock::acquire(&client, wait, force_unlock).await {
+                    println!("- {}: {} {:#}", target.name.red(), "FAILURE".red(), e);
+                    failed.push(target.name.clone());
+                    continue;
+                }
+                let started = chrono::Utc::now();
+                let mut result =
+                    install(&client, &ctx, &stages, previous.as_ref(), &mut journal).await;
+                // blocks of removed sections are only known, when the whole playbook runs
+                let prune = select.everything() && start_at_stage.is_none();
+                if let (Ok(reports), true) = (&result, prune) {
+                    if !aborted(reports) {
+                        match remote::profile::prune(&client, &ctx, &cfg.stages()).await {
+                            Ok(removed) => removed
+                                .iter()
+                                .for_each(|x| println!("~ {}: {}", x.yellow(), "REMOVED".yellow())),
+                            Err(e) => result = Err(e.context("stale profile blocks")),
                         }
                     }
                 }
-                None => {
-                    for (name, stage) in cfg.stages {
-                        remote::install(&client, &name, &stage).await.unwrap();
+                if let Ok(reports) = &result {
+                    let run = state::Run {
+                        started,
+                        finished: chrono::Utc::now(),
+                        operator: lock::operator(),
+                        playbook: file.display().to_string(),
+                        playbook_sha256: playbook_sha256.clone(),
+                        stages: reports.clone(),
+                        versions: state::versions(&client, ctx.probing, &stages)
+                            .await
+                            .unwrap_or_default(),
+                    };
+                    if let Err(e) = state::record(&client, run).await {
+                        warn!("failed to record the run: {}", e);
                     }
-                    if let Some(items) = &cfg.aliases {
-                        remote::alias::install(&client, items).await.unwrap();
+                }
+                // a lock left behind would stop the next run, so it fails the host
+                if let Err(e) = lock::release(&client).await {
+                    match result {
+                        Ok(_) => result = Err(e.context("release the lock")),
+                        Err(_) => warn!("failed to release the lock: {:#}", e),
                     }
-                    if let Some(items) = &cfg.exports {
-                        remote::export::install(&client, items).await.unwrap();
+                }
+                let reports = match result {
+                    Ok(reports) => reports,
+                    Err(e) => {
+                        println!("- {}: {} {:#}", target.name.red(), "FAILURE".red(), e);
+                        failed.push(target.name.clone());
+                        continue;
                     }
+                };
+                if aborted(&reports) {
+                    println!("- {}: {}", target.name.red(), "ABORTED".red());
+                    failed.push(target.name.clone());
                 }
             }
+            if !failed.is_empty() {
+                anyhow::bail!("install failed on {}", failed.join(", "));
+            }
         }
-        cli::Action::Check { file, stage } => {
-            let cfg: config::Config =
-                toml::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
-            let client = connect::get_client(ssh, &cfg).await.unwrap();
-            match stage {
-                Some(stage) => {
-                    if stage == "aliases" {
-                        if let Some(items) = &cfg.aliases {
-                            remote::alias::check(&client, items).await.unwrap();
-                        } else {
-                            panic!("no aliases declared");
-                        }
-                    } else if stage == "aliases" {
-                        if let Some(items) = &cfg.exports {
-                            remote::export::check(&client, items).await.unwrap();
-                        } else {
-                            panic!("no exports declared");
-                        }
-                    } else {
-                        remote::check(&client, &stage, &cfg.stages[&stage])
-                            .await
-                            .unwrap();
-                    }
+        cli::Action::Check { file, select } => {
+            let playbook = config::Config::load(&file)?;
+            let mut failed = vec![];
+            for (target, cfg) in resolved(&playbook, &ssh, &select)? {
+                let stages = selected(&cfg, &select)?;
+                if cfg.hosts.is_some() {
+                    println!("@ {}", target.name.cyan());
                 }
-                None => {
-                    for (name, stage) in cfg.stages {
-                        remote::check(&client, &name, &stage).await.unwrap();
+                let client = match connect::get_client(&target.ssh).await {
+                    Ok(client) => client,
+                    Err(e) => {
+                        println!("- {}: {} {:#}", target.name.red(), "FAILURE".red(), e);
+                        failed.push(target.name.clone());
+                        continue;
                     }
-                    if let Some(aliases) = &cfg.aliases {
-                        remote::alias::check(&client, aliases).await.unwrap();
+                };
+                let facts = match facts::gather(&client).await {
+                    Ok(facts) => facts,
+                    Err(e) => {
+                        println!(
+                            "- {}: {} facts: {:#}",
+                            target.name.red(),
+                            "FAILURE".red(),
+                            e
+                        );
+                        failed.push(target.name.clone());
+                        continue;
                     }
-                    if let Some(exports) = &cfg.exports {
-                        remote::export::check(&client, exports).await.unwrap();
+                };
+                let ctx = remote::Context {
+                    facts,
+                    selection: select.clone(),
+                    probing,
+                    ..remote::Context::new(&target)
+                };
+                if let Err(e) = check(&client, &ctx, &stages).await {
+                    println!("- {}: {} {:#}", target.name.red(), "FAILURE".red(), e);
+                    failed.push(target.name.clone());
+                    continue;
+                }
+                if select.everything() {
+                    let alias = "profiles";
+                    match remote::profile::check_stale(&client, &cfg.stages()).await {
+                        Ok(status @ prelude::Status::NotInstalled { .. }) => status.print(alias),
+                        Ok(_) => {}
+                        Err(e) => println!("- {}: {} {:#}", alias.red(), "FAILURE".red(), e),
                     }
                 }
             }
+            if !failed.is_empty() {
+                anyhow::bail!("check failed on {}", failed.join(", "));
+            }
+        }
+        cli::Action::Validate { file } => {
+            let cfg = config::Config::validate(&file)?;
+            println!(
+                "+ {}: {} ({} hosts)",
+                file.display().to_string().green(),
+                "OK".green(),
+                cfg.targets(&ssh).len()
+            );
+            return Ok(());
+        }
+        cli::Action::Schema => {
+            let schema = config::Config::schema();
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            return Ok(());
+        }
+        cli::Action::Convert { file, output, to } => {
+            let from = format::Format::from_path(&file);
+            let to = match (to, &output) {
+                (Some(to), _) => to,
+                (None, Some(output)) => format::Format::from_path(output),
+                (None, None) => anyhow::bail!("--to or --output is required"),
+            };
+            let contents = std::fs::read_to_string(&file)
+                .with_context(|| format!("read {}", file.display()))?;
+            let value = from
+                .parse(&contents)
+                .with_context(|| format!("parse {}", file.display()))?;
+            let converted = to.render(&value)?;
+            match output {
+                Some(output) => std::fs::write(&output, converted)
+                    .with_context(|| format!("write {}", output.display()))?,
+                None => print!("{}", converted),
+            }
+            return Ok(());
+        }
+        cli::Action::Init {
+            output,
+            hosts,
+            yes,
+            force,
+        } => {
+            if output.exists() && !force {
+                anyhow::bail!("{} exists, use --force to overwrite it", output.display());
+            }
+            if format::Format::from_path(&output) != format::Format::Toml {
+                anyhow::bail!("init writes TOML, use `convert` for other formats");
+            }
+            let mut starter = init::Starter {
+                remote_host: ssh.remote_host.clone().unwrap_or_default(),
+                remote_user: ssh.remote_user.clone().unwrap_or_default(),
+                remote_key_file: ssh.remote_key_file.clone().unwrap_or_default(),
+                hosts,
+            };
+            if !yes {
+                starter = starter.prompt()?;
+            }
+            std::fs::write(&output, starter.playbook())
+                .with_context(|| format!("write {}", output.display()))?;
+            println!(
+                "+ {}: {}",
+                output.display().to_string().green(),
+                "OK".green()
+            );
+            return Ok(());
+        }
+        cli::Action::Exec {
+            file,
+            limit,
+            sudo,
+            json,
+            command,
+        } => {
+            let select = cli::Selection {
+                limit,
+                ..Default::default()
+            };
+            let targets = match file {
+                Some(file) => config::Config::load(&file)?
+                    .targets(&ssh)
+                    .into_iter()
+                    .filter(|target| select.host(&target.name, &target.groups))
+                    .collect(),
+                None => vec![config::Target {
+                    name: ssh.remote_host.clone().unwrap_or_default(),
+                    ssh: ssh.clone(),
+                    vars: Default::default(),
+                    groups: vec![],
+                }],
+            };
+            if targets.is_empty() {
+                anyhow::bail!("no hosts match --limit {}", select.limit.join(","));
+            }
+            let results = exec::run(&targets, &exec::command(&command.join(" "), sudo)).await;
+            match json {
+                true => println!("{}", serde_json::to_string_pretty(&results)?),
+                false => results.iter().for_each(exec::HostOutput::print),
+            }
+            let failed = results
+                .iter()
+                .filter(|x| !x.ok())
+                .map(|x| x.host.as_str())
+                .collect::<Vec<_>>();
+            if !failed.is_empty() {
+                anyhow::bail!("command failed on {}", failed.join(", "));
+            }
+            return Ok(());
+        }
+        cli::Action::History { file, last } => {
+            let targets = match file {
+                Some(file) => config::Config::load(&file)?.targets(&ssh),
+                None => vec![config::Target {
+                    name: ssh.remote_host.clone().unwrap_or_default(),
+                    ssh: ssh.clone(),
+                    vars: Default::default(),
+                    groups: vec![],
+                }],
+            };
+            for target in targets {
+                println!("@ {}", target.name.cyan());
+                let state = match connect::get_client(&target.ssh).await {
+                    Ok(client) => state::read(&client).await,
+                    Err(e) => Err(e),
+                };
+                match state {
+                    Ok(state) => state::print(&state, last),
+                    Err(e) => println!("- {}: {} {:#}", target.name.red(), "FAILURE".red(), e),
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    let (commands, round_trips) = prelude::round_trips();
+    println!(
+        "~ {} commands in {} SSH round-trips ({} saved by batching), {:.1?} in probes, {:.1?}",
+        commands,
+        round_trips,
+        commands - round_trips,
+        prelude::probe_time(),
+        started.elapsed()
+    );
+    Ok(())
+}
+
+/// hosts of the playbook selected by `--limit`, with the playbook resolved
+/// for each of them, so an undefined variable fails before any host is touched
+fn resolved(
+    playbook: &config::Config,
+    ssh: &config::Ssh,
+    select: &cli::Selection,
+) -> anyhow::Result<Vec<(config::Target, config::Config)>> {
+    let targets = playbook
+        .targets(ssh)
+        .into_iter()
+        .filter(|target| select.host(&target.name, &target.groups))
+        .collect::<Vec<_>>();
+    if targets.is_empty() {
+        anyhow::bail!("no hosts match --limit {}", select.limit.join(","));
+    }
+    targets
+        .into_iter()
+        .map(|target| {
+            let cfg = playbook
+                .resolve(&target)
+                .context(format!("host {}", target.name))?;
+            Ok((target, cfg))
+        })
+        .collect()
+}
+
+/// stages selected on the command line, all of them by default.
+/// Stages without any selected module are left out
+fn selected<'a>(
+    cfg: &'a config::Config,
+    select: &cli::Selection,
+) -> anyhow::Result<Vec<(&'a str, &'a remote::Stage)>> {
+    let stages = cfg.stages();
+    for name in select.stage.iter().chain(&select.skip_stage) {
+        if !stages.iter().any(|(x, _)| x == name) {
+            anyhow::bail!("stage {} is not declared", name);
         }
     }
+    for module in &select.only_module {
+        if !remote::MODULES.contains(&module.replace('_', "-").as_str()) {
+            anyhow::bail!(
+                "unknown module {}, expected one of: {}",
+                module,
+                remote::MODULES.join(", ")
+            );
+        }
+    }
+    Ok(stages
+        .into_iter()
+        .filter(|(name, stage)| {
+            (select.stage.is_empty() || select.stage.iter().any(|x| x == name))
+                && !select.skip_stage.iter().any(|x| x == name)
+                && stage.selected(select)
+        })
+        .collect())
+}
 
+/// install the stages, the journal of the run is saved after every stage.
+/// Modules that succeeded in the previous run are skipped,
+/// the run stops at the stage, where a module with `on_error = "abort"` failed
+async fn install(
+    client: &Client,
+    ctx: &remote::Context,
+    stages: &[(&str, &remote::Stage)],
+    previous: Option<&journal::Journal>,
+    journal: &mut journal::Journal,
+) -> anyhow::Result<Vec<remote::StageReport>> {
+    let mut reports = vec![];
+    for (name, stage) in stages {
+        let done = previous.map(|x| x.done(name)).unwrap_or_default();
+        let report = remote::install(client, ctx, name, stage, &done, &reports).await?;
+        journal.merge(report.clone());
+        journal.save(&ctx.host)?;
+        reports.push(report);
+        if aborted(&reports) {
+            break;
+        }
+    }
+    Ok(reports)
+}
+
+/// the run was stopped by a module with `on_error = "abort"`
+fn aborted(reports: &[remote::StageReport]) -> bool {
+    reports
+        .iter()
+        .any(|x| x.stopped == Some(remote::OnError::Abort))
+}
+
+async fn check(
+    client: &Client,
+    ctx: &remote::Context,
+    stages: &[(&str, &remote::Stage)],
+) -> anyhow::Result<()> {
+    // results of the modules are carried over to the next stages, as by `install`
+    let mut results = config::Vars::new();
+    for (name, stage) in stages {
+        remote::check(client, ctx, name, stage, &mut results).await?;
+    }
     Ok(())
 }
@@ -1,9 +1,12 @@
+pub use crate::cmd::{quote, quote_path, Cmd};
 pub use anyhow::{bail, Context};
 pub use async_ssh2_tokio::client::{Client, CommandExecutedResult};
 pub use color_eyre::owo_colors::OwoColorize;
+pub use schemars::JsonSchema;
 pub use serde::{Deserialize, Serialize};
 pub use serde_aux::prelude::*;
 pub use std::collections::BTreeMap as Map;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 pub use tracing::*;
 
 pub enum Os {
@@ -12,36 +15,44 @@ pub enum Os {
     Unsupported,
 }
 
-pub async fn osinfo(client: &Client) -> Os {
-    match silent(&client, "uname -a").await {
-        Ok(out) => {
-            if out.output.contains("Ubuntu") {
-                Os::Ubuntu
-            } else if out.output.contains("Debian") {
-                Os::Debian
-            } else {
-                Os::Unsupported
-            }
+impl Os {
+    /// OS of the host from the output of `uname -a`
+    pub fn from_uname(output: &str) -> Self {
+        if output.contains("Ubuntu") {
+            Os::Ubuntu
+        } else if output.contains("Debian") {
+            Os::Debian
+        } else {
+            Os::Unsupported
         }
+    }
+}
+
+pub async fn osinfo(client: &Client) -> Os {
+    match silent(client, "uname -a").await {
+        Ok(out) => Os::from_uname(&out.output),
         Err(_) => Os::Unsupported,
     }
 }
 
 pub async fn which(client: &Client, cmd: &str) -> anyhow::Result<String> {
-    match silent(&client, &cmd).await {
-        Ok(out) => {
-            if out.exit_status == 0 {
-                return Ok(out.output.trim().to_string());
-            } else {
-                bail!(out.output.trim().replace("bash: line 1: ", "").to_string());
-            }
-        }
+    match silent(client, cmd).await {
+        Ok(out) => found(&out),
         Err(_) => bail!("not installed"),
     }
 }
 
+/// output of the command that succeeded, or its output as an error
+pub fn found(out: &CommandExecutedResult) -> anyhow::Result<String> {
+    if out.exit_status == 0 {
+        Ok(out.output.trim().to_string())
+    } else {
+        bail!(out.output.trim().replace("bash: line 1: ", "").to_string());
+    }
+}
+
 pub async fn some_output(client: &Client, cmd: &str) -> bool {
-    match silent(&client, &cmd).await {
+    match silent(client, cmd).await {
         Ok(out) => {
             if out.exit_status == 0 {
                 !out.output.trim().is_empty()
@@ -54,17 +65,433 @@ pub async fn some_output(client: &Client, cmd: &str) -> bool {
 }
 
 pub async fn file_exists(client: &Client, filename: &str) -> bool {
-    let cmd = format!("ls -1 {}", filename);
-    match silent(&client, &cmd).await {
+    let cmd = Cmd::new("ls -1").operands().path(filename);
+    match silent(client, &cmd).await {
         Ok(out) => out.exit_status == 0,
         Err(_) => false,
     }
 }
 
+/// options of the module, that accepts either a single table or an array of tables,
+/// i.e. `template = { src = "a", dest = "b" }` or `template = [{ ... }, { ... }]`
+#[derive(Debug, Default)]
+pub struct OneOrMany<T>(pub Vec<T>);
+
+impl<T> std::ops::Deref for OneOrMany<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for OneOrMany<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        format!("OneOrMany_{}", T::schema_name())
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        use schemars::schema::{ArrayValidation, InstanceType, SchemaObject, SubschemaValidation};
+
+        let many = SchemaObject {
+            instance_type: Some(InstanceType::Array.into()),
+            array: Some(Box::new(ArrayValidation {
+                items: Some(T::json_schema(gen).into()),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+        SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(vec![T::json_schema(gen), many.into()]),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+        .into()
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for OneOrMany<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+
+        struct Visitor<T>(std::marker::PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de>> serde::de::Visitor<'de> for Visitor<T> {
+            type Value = Vec<T>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a table or an array of tables")
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, seq: A) -> Result<Vec<T>, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(seq))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Vec<T>, A::Error> {
+                Ok(vec![T::deserialize(MapAccessDeserializer::new(map))?])
+            }
+        }
+
+        deserializer
+            .deserialize_any(Visitor(std::marker::PhantomData))
+            .map(OneOrMany)
+    }
+}
+
+/// hex-encoded sha256 of the local contents, same format as `sha256sum` prints
+pub fn sha256_hex(contents: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(contents))
+}
+
+/// sha256 of every remote file under the path (a file or a directory),
+/// keyed by the path as it was given, so `~/` prefixes are preserved
+pub async fn remote_checksums(
+    client: &Client,
+    path: &str,
+    sudo: bool,
+) -> anyhow::Result<Map<String, String>> {
+    let cmd = Cmd::new("echo").path(path).then(
+        Cmd::sudo(sudo, "find")
+            .path(path)
+            .raw("-type f -exec sha256sum {} + 2>/dev/null"),
+    );
+    let out = silent(client, &cmd).await?;
+    let mut lines = out.output.lines();
+    let expanded = lines.next().unwrap_or(path).trim().to_string();
+    let mut result = Map::new();
+    for line in lines {
+        if let Some((hash, file)) = line.split_once("  ") {
+            let file = file.replacen(&expanded, path, 1);
+            result.insert(file, hash.to_string());
+        }
+    }
+    Ok(result)
+}
+
+/// size of a base64 chunk sent in a single command, keeps every command
+/// well below the kernel limit for a single argument (128k)
+const UPLOAD_CHUNK: usize = 64 * 1024;
+
+/// command, that puts the temporary file in place of the destination, creating
+/// its parent directory. An existing file keeps its owner and mode, a new one gets
+/// `mode` or the default mode of the umask, never the 0600 of the temporary file
+pub(crate) fn place(tmp: &str, dest: &str, sudo: bool, mode: Option<&str>) -> Cmd {
+    let script = r#"mkdir -p -- "$(dirname -- "$2")" && if [ -e "$2" ] || [ -z "$3" ]; then cp --no-preserve=mode -- "$1" "$2"; else install -m "$3" -- "$1" "$2"; fi"#;
+    Cmd::sudo(sudo, "sh -c")
+        .arg(script)
+        .raw("sh")
+        .path(tmp)
+        .path(dest)
+        .arg(mode.unwrap_or_default())
+        .raw("2>&1")
+}
+
+/// upload contents into the remote file, creating its parent directory.
+/// Contents are sent base64-encoded in chunks into a temporary file,
+/// that is copied over the destination, see `place` for the mode
+#[instrument(skip(client, contents), level = "debug")]
+pub async fn upload(
+    client: &Client,
+    contents: &[u8],
+    dest: &str,
+    sudo: bool,
+    mode: Option<&str>,
+) -> anyhow::Result<()> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let tmp = run(client, "mktemp").await?.output.trim().to_string();
+    let encoded = general_purpose::STANDARD.encode(contents);
+    for chunk in encoded.as_bytes().chunks(UPLOAD_CHUNK) {
+        let cmd = Cmd::new("echo")
+            .arg(std::str::from_utf8(chunk)?)
+            .raw("| base64 -d >>")
+            .path(&tmp);
+        if let Err(e) = run(client, &cmd).await {
+            silent(client, &Cmd::new("rm -f").path(&tmp)).await?;
+            return Err(e);
+        }
+    }
+    let cmd = place(&tmp, dest, sudo, mode)
+        .then(Cmd::new("rc=$?"))
+        .then(Cmd::new("rm -f").path(&tmp))
+        .then(Cmd::new("exit $rc"));
+    run(client, &cmd).await?;
+    Ok(())
+}
+
+/// keep a timestamped copy of the remote file next to it
+pub async fn backup_file(client: &Client, path: &str, sudo: bool) -> anyhow::Result<()> {
+    let cmd = Cmd::sudo(sudo, "cp -p").operands().path(path).raw(&format!(
+        "{}.$(date +%Y%m%d%H%M%S).bak 2>&1",
+        quote_path(path)
+    ));
+    run(client, &cmd).await?;
+    Ok(())
+}
+
+/// replace contents of the remote file, that is edited in place.
+/// If the validation command is given, it is run against a temporary copy
+/// of the new contents (`%s` is replaced with its path) before the file is touched.
+/// A new file gets `mode`, see `place`
+#[instrument(skip(client, contents))]
+pub async fn replace_file(
+    client: &Client,
+    path: &str,
+    contents: &[u8],
+    sudo: bool,
+    mode: Option<&str>,
+    backup: bool,
+    validate: Option<&str>,
+) -> anyhow::Result<()> {
+    if let Some(validate) = validate {
+        let tmp = run(client, "mktemp").await?.output.trim().to_string();
+        upload(client, contents, &tmp, false, None).await?;
+        // the validation is a command of the playbook, only the path is quoted
+        let cmd = Cmd::sudo(sudo, &validate.replace("%s", &quote(&tmp))).raw("2>&1");
+        let result = run(client, &cmd).await;
+        silent(client, &Cmd::new("rm -f").path(&tmp)).await?;
+        result.context(format!("validation of {} failed", path))?;
+    }
+    if backup && file_exists(client, path).await {
+        backup_file(client, path, sudo).await?;
+    }
+    upload(client, contents, path, sudo, mode).await
+}
+
+/// contents of the remote file, `None` if the file is missing.
+/// The file is sent base64-encoded in the output of a single command
+#[instrument(skip(client), level = "debug")]
+pub async fn read_file(client: &Client, src: &str, sudo: bool) -> anyhow::Result<Option<Vec<u8>>> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    // exit status 3 tells that the file is missing, other failures are errors,
+    // so a file that can't be read is never taken for an empty one
+    let script = r#"if [ -e "$1" ]; then base64 -w0 "$1"; else exit 3; fi"#;
+    let cmd = Cmd::sudo(sudo, "sh -c")
+        .arg(script)
+        .raw("sh")
+        .path(src)
+        .raw("2>&1");
+    let out = silent(client, &cmd).await?;
+    match out.exit_status {
+        0 => {}
+        3 => return Ok(None),
+        status => bail!("read {}: {} (exit {})", src, out.output.trim(), status),
+    }
+    let contents = general_purpose::STANDARD
+        .decode(out.output.trim())
+        .context(format!("decode {}", src))?;
+    Ok(Some(contents))
+}
+
+/// contents of the remote text file, that is edited in place.
+/// Files that are not UTF-8 are refused, so they are never rewritten lossily
+pub async fn read_text(client: &Client, src: &str, sudo: bool) -> anyhow::Result<Option<String>> {
+    match read_file(client, src, sudo).await? {
+        None => Ok(None),
+        Some(contents) => match String::from_utf8(contents) {
+            Ok(text) => Ok(Some(text)),
+            Err(_) => bail!("{} is not a UTF-8 text file, it is not edited", src),
+        },
+    }
+}
+
+/// download the remote file and verify it against the expected checksum
+#[instrument(skip(client))]
+pub async fn download(
+    client: &Client,
+    src: &str,
+    sudo: bool,
+    expected: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let contents = read_file(client, src, sudo)
+        .await?
+        .context(format!("{} is missing", src))?;
+    let actual = sha256_hex(&contents);
+    if actual != expected {
+        bail!("checksum mismatch for {}: {} != {}", src, actual, expected);
+    }
+    Ok(contents)
+}
+
+/// unified diff between the actual contents of the file (`None` if it is missing)
+/// and the desired contents, empty if there is no difference
+pub fn unified_diff(path: &str, actual: Option<&str>, desired: &str) -> String {
+    let old = actual.unwrap_or("");
+    if old == desired {
+        return String::new();
+    }
+    let from = if actual.is_some() { path } else { "/dev/null" };
+    similar::TextDiff::from_lines(old, desired)
+        .unified_diff()
+        .context_radius(3)
+        .header(from, path)
+        .to_string()
+}
+
+/// unified diff for the remote file, binary files are not compared line by line
+pub fn file_diff(path: &str, actual: Option<&[u8]>, desired: &[u8]) -> String {
+    if actual == Some(desired) {
+        return String::new();
+    }
+    let actual_text = actual.map(std::str::from_utf8);
+    match (actual_text, std::str::from_utf8(desired)) {
+        (None, Ok(desired)) => unified_diff(path, None, desired),
+        (Some(Ok(actual)), Ok(desired)) => unified_diff(path, Some(actual), desired),
+        _ => format!("Binary files {} differ\n", path),
+    }
+}
+
+pub fn print_diff(text: &str) {
+    for line in text.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            println!("  {}", line.bold());
+        } else if line.starts_with('+') {
+            println!("  {}", line.green());
+        } else if line.starts_with('-') {
+            println!("  {}", line.red());
+        } else if line.starts_with("@@") {
+            println!("  {}", line.cyan());
+        } else {
+            println!("  {}", line);
+        }
+    }
+}
+
+/// commands that were run on the hosts, and SSH channels that were opened for them
+static COMMANDS: AtomicUsize = AtomicUsize::new(0);
+static ROUND_TRIPS: AtomicUsize = AtomicUsize::new(0);
+/// time spent waiting for `probe`, summed over the hosts, in microseconds
+static PROBING: AtomicU64 = AtomicU64::new(0);
+/// how the probes of a module are run, see `probe`
+#[derive(Debug, Clone, Copy)]
+pub struct Probing {
+    /// probes are combined into a single script
+    pub batch: bool,
+    /// SSH channels that may be open at once on a connection,
+    /// stays below the default `MaxSessions 10` of sshd
+    pub channels: usize,
+}
+
+impl Default for Probing {
+    fn default() -> Self {
+        Probing {
+            batch: false,
+            channels: 8,
+        }
+    }
+}
+
+/// number of commands and of SSH round-trips made so far
+pub fn round_trips() -> (usize, usize) {
+    (
+        COMMANDS.load(Ordering::Relaxed),
+        ROUND_TRIPS.load(Ordering::Relaxed),
+    )
+}
+
+/// time spent waiting for the probes, that `--batch` is meant to cut
+pub fn probe_time() -> std::time::Duration {
+    std::time::Duration::from_micros(PROBING.load(Ordering::Relaxed))
+}
+
+async fn execute(client: &Client, cmd: &str) -> anyhow::Result<CommandExecutedResult> {
+    COMMANDS.fetch_add(1, Ordering::Relaxed);
+    ROUND_TRIPS.fetch_add(1, Ordering::Relaxed);
+    Ok(client.execute(cmd).await?)
+}
+
+/// run the probes, that are read-only commands independent of each other.
+/// They are run concurrently on several channels of the connection,
+/// or in the batch mode they are combined into a single script, see `batch_script`
+#[instrument(skip(client), level = "debug")]
+pub async fn probe(
+    client: &Client,
+    probing: Probing,
+    cmds: &[Cmd],
+) -> anyhow::Result<Vec<CommandExecutedResult>> {
+    let started = std::time::Instant::now();
+    let result = probe_all(client, probing, cmds).await;
+    let elapsed = started.elapsed().as_micros() as u64;
+    PROBING.fetch_add(elapsed, Ordering::Relaxed);
+    result
+}
+
+async fn probe_all(
+    client: &Client,
+    probing: Probing,
+    cmds: &[Cmd],
+) -> anyhow::Result<Vec<CommandExecutedResult>> {
+    use futures::stream::{self, StreamExt, TryStreamExt};
+
+    if !probing.batch || cmds.len() < 2 {
+        // results are kept in the order of the commands
+        return stream::iter(cmds)
+            .map(|cmd| silent(client, cmd))
+            .buffered(probing.channels.max(1))
+            .try_collect()
+            .await;
+    }
+    let out = silent(client, &batch_script(cmds)).await?;
+    COMMANDS.fetch_add(cmds.len() - 1, Ordering::Relaxed);
+    parse_probes(&out.output, cmds.len())
+}
+
+/// script, that runs the commands one by one and prints a JSON line with the exit status
+/// and the base64-encoded output of every command. The output is the same as of the
+/// command run on its own: stdout with its trailing newlines, which `$(...)` would strip
+/// without the `x` appended, and stderr goes to the stderr of the script, that is dropped.
+/// Every command runs in a subshell of its own, so `exit` ends only the command
+fn batch_script(cmds: &[Cmd]) -> String {
+    cmds.iter()
+        .map(|cmd| {
+            format!(
+                "o=$( (\n{}\n); rc=$?; printf x; exit $rc); rc=$?; o=${{o%x}}\n\
+                printf '{{\"status\":%d,\"output\":\"%s\"}}\\n' \"$rc\" \"$(printf %s \"$o\" | base64 -w0)\"",
+                cmd
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Deserialize)]
+struct ProbeResult {
+    status: u32,
+    output: String,
+}
+
+fn parse_probes(output: &str, expected: usize) -> anyhow::Result<Vec<CommandExecutedResult>> {
+    use base64::{engine::general_purpose, Engine as _};
+
+    let mut results = vec![];
+    for line in output.lines().filter(|x| x.starts_with('{')) {
+        let result: ProbeResult = serde_json::from_str(line).context("probe result")?;
+        let output = general_purpose::STANDARD
+            .decode(result.output)
+            .context("probe output")?;
+        results.push(CommandExecutedResult {
+            output: String::from_utf8_lossy(&output).to_string(),
+            exit_status: result.status,
+        });
+    }
+    if results.len() != expected {
+        bail!("{} of {} probes returned a result", results.len(), expected);
+    }
+    Ok(results)
+}
+
 /// run and fail on any exit_status that is not 0
 #[instrument(skip(client), level = "debug")]
 pub async fn run(client: &Client, cmd: &str) -> anyhow::Result<CommandExecutedResult> {
-    let exec_result: CommandExecutedResult = client.execute(&cmd).await?;
+    let exec_result: CommandExecutedResult = execute(client, cmd).await?;
     if exec_result.exit_status == 0 {
         debug!("{} {:?}", cmd, exec_result);
         Ok(exec_result)
@@ -77,7 +504,7 @@ pub async fn run(client: &Client, cmd: &str) -> anyhow::Result<CommandExecutedRe
 /// run and ingore the possible erro
 #[instrument(skip(client), level = "debug")]
 pub async fn silent(client: &Client, cmd: &str) -> anyhow::Result<CommandExecutedResult> {
-    let exec_result: CommandExecutedResult = client.execute(&cmd).await?;
+    let exec_result: CommandExecutedResult = execute(client, cmd).await?;
     debug!("{} {:?}", cmd, exec_result);
     Ok(exec_result)
 }
@@ -93,6 +520,9 @@ pub enum Status {
         success: Vec<String>,
         #[serde(skip_serializing_if = "Vec::is_empty")]
         fail: Vec<String>,
+        /// unified diffs of the files that would be changed by the installation
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        diff: Vec<String>,
     },
 }
 impl std::fmt::Debug for Status {
@@ -100,17 +530,17 @@ impl std::fmt::Debug for Status {
         match self {
             Status::Installed { success } => {
                 let mut out = f.debug_struct("Installed");
-                if success.len() > 0 {
+                if !success.is_empty() {
                     out.field("success", success);
                 }
                 out.finish()
             }
-            Status::NotInstalled { success, fail } => {
+            Status::NotInstalled { success, fail, .. } => {
                 let mut out = f.debug_struct("NotInstalled");
-                if success.len() > 0 {
+                if !success.is_empty() {
                     out.field("success", success);
                 }
-                if fail.len() > 0 {
+                if !fail.is_empty() {
                     out.field("fail", fail);
                 }
                 out.finish()
@@ -127,19 +557,123 @@ impl Status {
             Status::NotInstalled {
                 success,
                 fail: failure,
+                diff: vec![],
             }
         }
     }
 
+    /// attach unified diffs of the files that are not in the desired state
+    pub fn with_diff(mut self, diffs: Vec<String>) -> Self {
+        if let Status::NotInstalled { diff, .. } = &mut self {
+            *diff = diffs;
+        }
+        self
+    }
+
+    /// unified diffs of the files, that are not in the desired state
+    pub fn diffs(&self) -> &[String] {
+        match self {
+            Status::Installed { .. } => &[],
+            Status::NotInstalled { diff, .. } => diff,
+        }
+    }
+
     pub fn print(&self, alias: &str) {
         let out = format!("{:?}", self);
         match &self {
             Status::Installed { .. } => {
-                println!("+ {}: {}", alias.green(), format!("{}", out).green());
+                println!("+ {}: {}", alias.green(), out.to_string().green());
             }
-            Status::NotInstalled { .. } => {
-                println!("+ {}: {}", alias.red(), format!("{}", out).red());
+            Status::NotInstalled { diff, .. } => {
+                println!("+ {}: {}", alias.red(), out.to_string().red());
+                for text in diff {
+                    print_diff(text);
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn it_places_files_with_the_default_mode() {
+        let dir =
+            std::env::temp_dir().join(format!("remote-playbook-place-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).display().to_string();
+        let mode = |name: &str| {
+            std::fs::metadata(dir.join(name))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        let place = |name: &str, mode: Option<&str>| {
+            std::fs::write(dir.join("tmp"), name).unwrap();
+            std::fs::set_permissions(dir.join("tmp"), std::fs::Permissions::from_mode(0o600))
+                .unwrap();
+            let cmd = place(&path("tmp"), &path(name), false, mode);
+            let status = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(format!("umask 022; {}", cmd))
+                .status()
+                .unwrap();
+            assert!(status.success(), "{}", cmd);
+        };
+        place("sub/new.conf", None);
+        assert_eq!(mode("sub/new.conf"), 0o644);
+        place("new.sh", Some("0755"));
+        assert_eq!(mode("new.sh"), 0o755);
+        std::fs::write(dir.join("secret"), "").unwrap();
+        std::fs::set_permissions(dir.join("secret"), std::fs::Permissions::from_mode(0o640))
+            .unwrap();
+        place("secret", Some("0644"));
+        assert_eq!(mode("secret"), 0o640);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("secret")).unwrap(),
+            "secret"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn it_batches_probes_with_the_same_output() {
+        let cmds = [
+            Cmd::new("printf 'a\\n\\n'"),
+            Cmd::new("echo out; echo err >&2; false"),
+            Cmd::new("ls /nonexistent").raw("2>&1"),
+            Cmd::new("printf 'no newline'; exit 3"),
+            Cmd::new("true"),
+        ];
+        let sh = |script: &str| {
+            std::process::Command::new("sh")
+                .arg("-c")
+                .arg(script)
+                .output()
+        };
+        let batched = sh(&batch_script(&cmds)).unwrap();
+        let batched = parse_probes(&String::from_utf8_lossy(&batched.stdout), cmds.len()).unwrap();
+        for (cmd, batched) in cmds.iter().zip(batched) {
+            let out = sh(cmd).unwrap();
+            let unbatched = CommandExecutedResult {
+                output: String::from_utf8_lossy(&out.stdout).to_string(),
+                exit_status: out.status.code().unwrap() as u32,
+            };
+            assert_eq!(batched, unbatched, "{}", cmd);
+        }
+    }
+
+    #[test]
+    fn it_parses_batched_probes() {
+        let output = "{\"status\":0,\"output\":\"aGVsbG8=\"}\n{\"status\":2,\"output\":\"\"}\n";
+        let results = parse_probes(output, 2).unwrap();
+        assert_eq!(results[0].output, "hello");
+        assert_eq!(results[0].exit_status, 0);
+        assert_eq!(results[1].exit_status, 2);
+        assert!(parse_probes(output, 3).is_err());
+    }
+}
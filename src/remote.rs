@@ -1,24 +1,39 @@
-pub mod alias; // this module is treated as aseparate section
-pub mod export; // this module is treated as aseparate section
+pub mod profile;
+pub use profile::ProfileOptions;
 
 pub mod apt;
 pub use apt::AptOptions;
 
+pub mod blockinfile;
+pub use blockinfile::BlockinfileOptions;
+
+pub mod copy;
+pub use copy::CopyOptions;
+
 pub mod docker;
 pub use docker::DockerOptions;
 
+pub mod fetch;
+pub use fetch::FetchOptions;
+
 pub mod git;
 pub use git::GitOptions;
 
 pub mod keys;
 pub use keys::KeysOptions;
 
+pub mod lineinfile;
+pub use lineinfile::LineinfileOptions;
+
 pub mod mkdir;
 pub use mkdir::MkdirOptions;
 
 pub mod mount;
 pub use mount::MountOptions;
 
+pub mod template;
+pub use template::TemplateOptions;
+
 pub mod terraform;
 pub use terraform::TerraformOptions;
 
@@ -31,178 +46,729 @@ pub use node_exporter::NodeExporterOptions;
 pub mod docker_stats;
 pub use docker_stats::DockerStatsOptions;
 
+pub mod step;
+pub use step::{Answer, Declined};
+
+pub mod task;
+pub use task::{OnError, Task};
+
 // use crate::prelude::*;
+use crate::cli::Selection;
+use crate::config::{Target, Vars};
+use crate::expr::Expr;
+use crate::prelude::{print_diff, OneOrMany, Probing, Status};
 use async_ssh2_tokio::client::Client;
 use color_eyre::owo_colors::OwoColorize;
-use serde::Deserialize;
+use profile::Entry;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
 use tracing::*;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Stage {
-    pub mount: Option<MountOptions>,
-    pub mkdir: Option<MkdirOptions>,
-    pub apt: Option<AptOptions>,
-    pub keys: Option<KeysOptions>,
-    pub git: Option<GitOptions>,
-    pub aws: Option<AwsOptions>,
-    pub docker: Option<DockerOptions>,
-    pub terraform: Option<TerraformOptions>,
+    /// what happens when a module of the stage fails, unless the module declares it
+    pub on_error: Option<OnError>,
+    /// the stage is applied only when the expression holds
+    pub when: Option<Expr>,
+    /// tags to select the stage with `--tags`, apply to all its modules
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub mount: Option<Task<MountOptions>>,
+    pub mkdir: Option<Task<MkdirOptions>>,
+    pub apt: Option<Task<AptOptions>>,
+    pub copy: Option<Task<OneOrMany<CopyOptions>>>,
+    pub template: Option<Task<OneOrMany<TemplateOptions>>>,
+    pub lineinfile: Option<Task<OneOrMany<LineinfileOptions>>>,
+    pub blockinfile: Option<Task<OneOrMany<BlockinfileOptions>>>,
+    pub keys: Option<Task<KeysOptions>>,
+    pub git: Option<Task<GitOptions>>,
+    pub aws: Option<Task<AwsOptions>>,
+    pub docker: Option<Task<DockerOptions>>,
+    pub terraform: Option<Task<TerraformOptions>>,
     #[serde(alias = "node-exporter")]
-    pub node_exporter: Option<NodeExporterOptions>,
+    pub node_exporter: Option<Task<NodeExporterOptions>>,
     #[serde(alias = "docker-stats")]
-    pub docker_stats: Option<DockerStatsOptions>,
+    pub docker_stats: Option<Task<DockerStatsOptions>>,
+    pub fetch: Option<Task<OneOrMany<FetchOptions>>>,
+    pub aliases: Option<Task<ProfileOptions>>,
+    pub exports: Option<Task<ProfileOptions>>,
 }
 
-#[instrument(skip(client))]
-pub async fn install(client: &Client, name: &str, stage: &Stage) -> anyhow::Result<()> {
-    println!("= {}", name.yellow());
+/// modules in the order they are installed
+pub const MODULES: [&str; 17] = [
+    "mount",
+    "mkdir",
+    "keys",
+    "git",
+    "apt",
+    "copy",
+    "template",
+    "lineinfile",
+    "blockinfile",
+    "docker",
+    "terraform",
+    "aws",
+    "node-exporter",
+    "docker-stats",
+    "fetch",
+    "aliases",
+    "exports",
+];
 
-    if let Some(opt) = &stage.mount {
-        let alias = "mount";
-        match mount::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+impl Stage {
+    /// declared modules with their tags, in the order they are installed
+    pub fn modules(&self) -> Vec<(&'static str, &[String])> {
+        let tags = [
+            self.mount.as_ref().map(|x| &x.tags),
+            self.mkdir.as_ref().map(|x| &x.tags),
+            self.keys.as_ref().map(|x| &x.tags),
+            self.git.as_ref().map(|x| &x.tags),
+            self.apt.as_ref().map(|x| &x.tags),
+            self.copy.as_ref().map(|x| &x.tags),
+            self.template.as_ref().map(|x| &x.tags),
+            self.lineinfile.as_ref().map(|x| &x.tags),
+            self.blockinfile.as_ref().map(|x| &x.tags),
+            self.docker.as_ref().map(|x| &x.tags),
+            self.terraform.as_ref().map(|x| &x.tags),
+            self.aws.as_ref().map(|x| &x.tags),
+            self.node_exporter.as_ref().map(|x| &x.tags),
+            self.docker_stats.as_ref().map(|x| &x.tags),
+            self.fetch.as_ref().map(|x| &x.tags),
+            self.aliases.as_ref().map(|x| &x.tags),
+            self.exports.as_ref().map(|x| &x.tags),
+        ];
+        MODULES
+            .into_iter()
+            .zip(tags)
+            .filter_map(|(alias, tags)| Some((alias, tags?.as_slice())))
+            .collect()
+    }
+
+    /// whether any module of the stage is selected
+    pub fn selected(&self, selection: &Selection) -> bool {
+        self.modules()
+            .into_iter()
+            .any(|(alias, tags)| selection.module(alias, &self.tags, tags))
+    }
+}
+
+/// host that is being processed, available to the modules
+#[derive(Debug, Default)]
+pub struct Context {
+    /// name of the host in the inventory
+    pub host: String,
+    /// variables of the playbook, with variables of the host applied
+    pub vars: Vars,
+    /// what happens when a module fails, unless its stage or the module declares it
+    pub on_error: Option<OnError>,
+    /// facts gathered from the host
+    pub facts: Vars,
+    /// modules selected on the command line
+    pub selection: Selection,
+    /// ask before every module and destructive command, see `install --step`
+    pub step: bool,
+    /// print the changes of every module before it is applied
+    pub diff: bool,
+    /// how read-only commands of the modules are run, see `--batch` and `--channels`
+    pub probing: Probing,
+}
+
+impl Context {
+    pub fn new(target: &Target) -> Self {
+        Context {
+            host: target.name.clone(),
+            vars: target.vars.clone(),
+            on_error: None,
+            facts: Vars::new(),
+            selection: Selection::default(),
+            step: false,
+            diff: false,
+            probing: Probing::default(),
         }
     }
-    if let Some(opt) = &stage.mkdir {
-        let alias = "mkdir";
-        match mkdir::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+
+    /// answer of the operator with `--step`, the action is confirmed otherwise
+    pub fn confirm(&self, action: &str) -> anyhow::Result<Answer> {
+        match self.step {
+            true => step::ask(action),
+            false => Ok(Answer::Yes),
         }
     }
-    if let Some(opt) = &stage.keys {
-        let alias = "keys";
-        match keys::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+
+    /// destructive command inside a module, confirmed on its own with `--step`.
+    /// A declined command leaves the module out, see `Declined`
+    pub fn approve(&self, action: &str) -> anyhow::Result<()> {
+        match self.confirm(action)? {
+            Answer::Yes => Ok(()),
+            answer => Err(Declined(answer).into()),
         }
     }
-    if let Some(opt) = &stage.git {
-        let alias = "git";
-        match git::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+
+    /// names of `when` expressions, with the results of the modules in this run
+    pub fn scope(&self, results: &Vars) -> serde_json::Value {
+        serde_json::json!({
+            "facts": self.facts,
+            "vars": self.vars,
+            "results": results,
+            "host": self.host,
+        })
+    }
+
+    /// whether the `when` expression holds, prints the skipped stage or module
+    fn applies(&self, alias: &str, when: &Option<Expr>, results: &Vars) -> anyhow::Result<bool> {
+        let Some(when) = when else {
+            return Ok(true);
+        };
+        let holds = when.test(&self.scope(results))?;
+        if !holds {
+            println!(
+                "~ {}: {} when {}",
+                alias.yellow(),
+                "SKIPPED".yellow(),
+                when.src()
+            );
         }
+        Ok(holds)
     }
-    if let Some(opt) = &stage.apt {
-        let alias = "apt";
-        match apt::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
+}
+
+/// result of the module in the stage, recorded in the state of the host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModuleReport {
+    pub module: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// results of the modules in the stage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageReport {
+    pub name: String,
+    pub modules: Vec<ModuleReport>,
+    /// policy of the failed module, that stopped the stage: `skip_stage` or `abort`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<OnError>,
+}
+
+/// print the result of the module, as it was installed
+fn report(alias: &str, result: anyhow::Result<()>) -> ModuleReport {
+    match &result {
+        Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
+        Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+    }
+    ModuleReport {
+        module: alias.to_string(),
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
+/// `ok` or `failed` of the modules in the reports, the latest result wins
+fn results(reports: &[StageReport]) -> Vars {
+    reports
+        .iter()
+        .flat_map(|stage| &stage.modules)
+        .map(|module| {
+            let status = match module.error {
+                None => "ok",
+                Some(_) => "failed",
+            };
+            (module.module.clone(), status.into())
+        })
+        .collect()
+}
+
+/// modules of the stage, that are run in order until a failure stops the stage
+struct Runner<'a> {
+    ctx: &'a Context,
+    stage: &'a Stage,
+    /// modules, that are done already in the run that is resumed
+    done: &'a [String],
+    /// `ok` or `failed` of the modules, that were applied in this run
+    results: Vars,
+    modules: Vec<ModuleReport>,
+    stopped: Option<OnError>,
+}
+
+impl Runner<'_> {
+    /// policy of the module, declared by the module, its stage or the playbook
+    fn on_error<T>(&self, alias: &str, task: &Task<T>) -> OnError {
+        task.on_error
+            .or(self.stage.on_error)
+            .or(self.ctx.on_error)
+            .unwrap_or_else(|| OnError::default_for(alias))
+    }
+
+    /// with `--step` or `--diff`, print what the module is about to change
+    /// and ask whether to apply it
+    async fn plan(
+        &self,
+        alias: &str,
+        check: impl Future<Output = anyhow::Result<Status>>,
+    ) -> anyhow::Result<Answer> {
+        if !self.ctx.step && !self.ctx.diff {
+            return Ok(Answer::Yes);
+        }
+        // pending changes are printed as by `check`
+        match check.await {
+            Ok(status) if self.ctx.step => status.print(alias),
+            Ok(status) => status.diffs().iter().for_each(|text| print_diff(text)),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
+        self.ctx.confirm(&format!("install {}", alias))
     }
-    if let Some(opt) = &stage.docker {
-        let alias = "docker";
-        match docker::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+
+    /// the module, or a command inside it, was declined with `--step`
+    fn decline(&mut self, alias: &str, answer: Answer) {
+        println!("~ {}: {}", alias.yellow(), "SKIPPED".yellow());
+        match answer {
+            Answer::Skip => self.stopped = Some(OnError::SkipStage),
+            Answer::Abort => self.stopped = Some(OnError::Abort),
+            Answer::Yes | Answer::No => {}
         }
     }
-    if let Some(opt) = &stage.terraform {
-        let alias = "terraform";
-        match terraform::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+
+    /// run the module, unless it is done already, the stage is stopped or `when` doesn't hold
+    async fn apply<T>(
+        &mut self,
+        alias: &str,
+        task: &Task<T>,
+        check: impl Future<Output = anyhow::Result<Status>>,
+        install: impl Future<Output = anyhow::Result<()>>,
+    ) {
+        if !self
+            .ctx
+            .selection
+            .module(alias, &self.stage.tags, &task.tags)
+        {
+            return;
+        }
+        if self.stopped.is_some() {
+            println!("~ {}: {}", alias.yellow(), "SKIPPED".yellow());
+            return;
         }
+        if self.done.iter().any(|x| x == alias) {
+            println!("+ {}: {}", alias.green(), "DONE".green());
+            self.modules.push(ModuleReport {
+                module: alias.to_string(),
+                error: None,
+            });
+            return;
+        }
+        let result = match self.ctx.applies(alias, &task.when, &self.results) {
+            Ok(false) => return,
+            Ok(true) => match self.plan(alias, check).await {
+                Ok(Answer::Yes) => install.await,
+                Ok(answer) => return self.decline(alias, answer),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        if let Some(Declined(answer)) = result.as_ref().err().and_then(|e| e.downcast_ref()) {
+            return self.decline(alias, *answer);
+        }
+        let report = report(alias, result);
+        let status = match report.error {
+            None => "ok",
+            Some(_) => "failed",
+        };
+        self.results.insert(alias.to_string(), status.into());
+        if report.error.is_some() {
+            let policy = self.on_error(alias, task);
+            if policy != OnError::Continue {
+                self.stopped = Some(policy);
+            }
+        }
+        self.modules.push(report);
     }
-    if let Some(opt) = &stage.aws {
-        let alias = "aws";
-        match aws::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+}
+
+/// install modules of the stage, except the modules that are `done`.
+/// A failed module stops the stage, when its policy is `skip_stage` or `abort`.
+/// `previous` stages of this run are available to `when` as `results`
+#[instrument(skip(client, ctx))]
+pub async fn install(
+    client: &Client,
+    ctx: &Context,
+    name: &str,
+    stage: &Stage,
+    done: &[String],
+    previous: &[StageReport],
+) -> anyhow::Result<StageReport> {
+    println!("= {}", name.yellow());
+    let mut runner = Runner {
+        ctx,
+        stage,
+        done,
+        results: results(previous),
+        modules: vec![],
+        stopped: None,
+    };
+    match ctx.applies(name, &stage.when, &runner.results) {
+        Ok(true) => {}
+        Ok(false) => {
+            return Ok(StageReport {
+                name: name.to_string(),
+                modules: vec![],
+                stopped: None,
+            })
         }
+        // a `when` that can't be evaluated fails the stage, the policy of the stage
+        // or the playbook tells whether the run goes on
+        Err(e) => {
+            let policy = stage
+                .on_error
+                .or(ctx.on_error)
+                .unwrap_or_else(|| OnError::default_for("when"));
+            return Ok(StageReport {
+                name: name.to_string(),
+                modules: vec![report("when", Err(e))],
+                stopped: Some(policy).filter(|x| *x != OnError::Continue),
+            });
+        }
+    }
+
+    if let Some(opt) = &stage.mount {
+        runner
+            .apply(
+                "mount",
+                opt,
+                mount::on_check(client, ctx, opt),
+                mount::on_install(client, ctx, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.mkdir {
+        runner
+            .apply(
+                "mkdir",
+                opt,
+                mkdir::on_check(client, ctx, opt),
+                mkdir::on_install(client, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.keys {
+        runner
+            .apply(
+                "keys",
+                opt,
+                keys::on_check(client, opt),
+                keys::on_install(client, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.git {
+        runner
+            .apply(
+                "git",
+                opt,
+                git::on_check(client, opt),
+                git::on_install(client, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.apt {
+        runner
+            .apply(
+                "apt",
+                opt,
+                apt::on_check(client, ctx, opt),
+                apt::on_install(client, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.copy {
+        runner
+            .apply(
+                "copy",
+                opt,
+                copy::on_check(client, opt),
+                copy::on_install(client, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.template {
+        runner
+            .apply(
+                "template",
+                opt,
+                template::on_check(client, ctx, opt),
+                template::on_install(client, ctx, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.lineinfile {
+        runner
+            .apply(
+                "lineinfile",
+                opt,
+                lineinfile::on_check(client, opt),
+                lineinfile::on_install(client, ctx, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.blockinfile {
+        runner
+            .apply(
+                "blockinfile",
+                opt,
+                blockinfile::on_check(client, opt),
+                blockinfile::on_install(client, ctx, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.docker {
+        runner
+            .apply(
+                "docker",
+                opt,
+                docker::on_check(client, ctx, opt),
+                docker::on_install(client, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.terraform {
+        runner
+            .apply(
+                "terraform",
+                opt,
+                terraform::on_check(client, ctx, opt),
+                terraform::on_install(client, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.aws {
+        runner
+            .apply(
+                "aws",
+                opt,
+                aws::on_check(client, ctx, opt),
+                aws::on_install(client, opt),
+            )
+            .await;
     }
     if let Some(opt) = &stage.node_exporter {
-        let alias = "node-exporter";
-        match node_exporter::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
-        }
+        runner
+            .apply(
+                "node-exporter",
+                opt,
+                node_exporter::on_check(client, opt),
+                node_exporter::on_install(client, opt),
+            )
+            .await;
     }
     if let Some(opt) = &stage.docker_stats {
-        let alias = "docker-stats";
-        match docker_stats::on_install(client, opt).await {
-            Ok(_) => println!("+ {}: {}", alias.green(), "OK".green()),
-            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+        runner
+            .apply(
+                "docker-stats",
+                opt,
+                docker_stats::on_check(client, opt),
+                docker_stats::on_install(client, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.fetch {
+        runner
+            .apply(
+                "fetch",
+                opt,
+                fetch::on_check(client, ctx, opt),
+                fetch::on_install(client, ctx, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.aliases {
+        runner
+            .apply(
+                "aliases",
+                opt,
+                profile::on_check(client, Entry::Alias, name, opt),
+                profile::on_install(client, ctx, Entry::Alias, name, opt),
+            )
+            .await;
+    }
+    if let Some(opt) = &stage.exports {
+        runner
+            .apply(
+                "exports",
+                opt,
+                profile::on_check(client, Entry::Export, name, opt),
+                profile::on_install(client, ctx, Entry::Export, name, opt),
+            )
+            .await;
+    }
+    Ok(StageReport {
+        name: name.to_string(),
+        modules: runner.modules,
+        stopped: runner.stopped,
+    })
+}
+
+/// options of the module, if it is selected and its `when` holds.
+/// Nothing is installed by `check`, so the module is taken as `ok` in `results`
+/// of the modules after it, as it would be after a successful `install`
+fn enabled<'a, T>(
+    ctx: &Context,
+    stage: &Stage,
+    alias: &str,
+    task: &'a Option<Task<T>>,
+    results: &mut Vars,
+) -> Option<&'a Task<T>> {
+    let task = task.as_ref()?;
+    if !ctx.selection.module(alias, &stage.tags, &task.tags) {
+        return None;
+    }
+    match ctx.applies(alias, &task.when, results) {
+        Ok(true) => {
+            results.insert(alias.to_string(), "ok".into());
+            Some(task)
+        }
+        Ok(false) => None,
+        Err(e) => {
+            println!("- {}: {} {}", alias.red(), "FAILURE".red(), e);
+            None
         }
     }
-    Ok(())
 }
 
-#[instrument(skip(client))]
-pub async fn check(client: &Client, name: &str, stage: &Stage) -> anyhow::Result<()> {
+#[instrument(skip(client, ctx))]
+pub async fn check(
+    client: &Client,
+    ctx: &Context,
+    name: &str,
+    stage: &Stage,
+    results: &mut Vars,
+) -> anyhow::Result<()> {
     println!("= {}", name.yellow());
+    match ctx.applies(name, &stage.when, results) {
+        Ok(true) => {}
+        Ok(false) => return Ok(()),
+        Err(e) => {
+            println!("- {}: {} {}", "when".red(), "FAILURE".red(), e);
+            return Ok(());
+        }
+    }
 
-    if let Some(opt) = &stage.mount {
+    if let Some(opt) = enabled(ctx, stage, "mount", &stage.mount, results) {
         let alias = "mount";
-        match mount::on_check(client, opt).await {
+        match mount::on_check(client, ctx, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.mkdir {
+    if let Some(opt) = enabled(ctx, stage, "mkdir", &stage.mkdir, results) {
         let alias = "mkdir";
-        match mkdir::on_check(client, opt).await {
+        match mkdir::on_check(client, ctx, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.apt {
+    if let Some(opt) = enabled(ctx, stage, "apt", &stage.apt, results) {
         let alias = "apt";
-        match apt::on_check(client, opt).await {
+        match apt::on_check(client, ctx, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.keys {
+    if let Some(opt) = enabled(ctx, stage, "copy", &stage.copy, results) {
+        let alias = "copy";
+        match copy::on_check(client, opt).await {
+            Ok(status) => status.print(alias),
+            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+        }
+    }
+    if let Some(opt) = enabled(ctx, stage, "template", &stage.template, results) {
+        let alias = "template";
+        match template::on_check(client, ctx, opt).await {
+            Ok(status) => status.print(alias),
+            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+        }
+    }
+    if let Some(opt) = enabled(ctx, stage, "lineinfile", &stage.lineinfile, results) {
+        let alias = "lineinfile";
+        match lineinfile::on_check(client, opt).await {
+            Ok(status) => status.print(alias),
+            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+        }
+    }
+    if let Some(opt) = enabled(ctx, stage, "blockinfile", &stage.blockinfile, results) {
+        let alias = "blockinfile";
+        match blockinfile::on_check(client, opt).await {
+            Ok(status) => status.print(alias),
+            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+        }
+    }
+    if let Some(opt) = enabled(ctx, stage, "keys", &stage.keys, results) {
         let alias = "keys";
         match keys::on_check(client, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.git {
+    if let Some(opt) = enabled(ctx, stage, "git", &stage.git, results) {
         let alias = "git";
         match git::on_check(client, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.docker {
+    if let Some(opt) = enabled(ctx, stage, "docker", &stage.docker, results) {
         let alias = "docker";
-        match docker::on_check(client, opt).await {
+        match docker::on_check(client, ctx, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.terraform {
+    if let Some(opt) = enabled(ctx, stage, "terraform", &stage.terraform, results) {
         let alias = "terraform";
-        match terraform::on_check(client, opt).await {
+        match terraform::on_check(client, ctx, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.aws {
+    if let Some(opt) = enabled(ctx, stage, "aws", &stage.aws, results) {
         let alias = "aws";
-        match aws::on_check(client, opt).await {
+        match aws::on_check(client, ctx, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.node_exporter {
+    if let Some(opt) = enabled(ctx, stage, "node-exporter", &stage.node_exporter, results) {
         let alias = "node-exporter";
         match node_exporter::on_check(client, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
-    if let Some(opt) = &stage.docker_stats {
+    if let Some(opt) = enabled(ctx, stage, "docker-stats", &stage.docker_stats, results) {
         let alias = "docker-stats";
         match docker_stats::on_check(client, opt).await {
             Ok(status) => status.print(alias),
             Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
         }
     }
+    if let Some(opt) = enabled(ctx, stage, "fetch", &stage.fetch, results) {
+        let alias = "fetch";
+        match fetch::on_check(client, ctx, opt).await {
+            Ok(status) => status.print(alias),
+            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+        }
+    }
+    if let Some(opt) = enabled(ctx, stage, "aliases", &stage.aliases, results) {
+        let alias = "aliases";
+        match profile::on_check(client, Entry::Alias, name, opt).await {
+            Ok(status) => status.print(alias),
+            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+        }
+    }
+    if let Some(opt) = enabled(ctx, stage, "exports", &stage.exports, results) {
+        let alias = "exports";
+        match profile::on_check(client, Entry::Export, name, opt).await {
+            Ok(status) => status.print(alias),
+            Err(e) => println!("- {}: {} {}", alias.red(), "FAILURE".red(), e),
+        }
+    }
     Ok(())
 }
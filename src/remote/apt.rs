@@ -1,21 +1,23 @@
 use crate::prelude::*;
+use crate::remote;
 
-#[derive(Debug, Default, Deserialize)]
+/// install packages with apt-get
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct AptOptions {
+    /// packages to be installed
     pub install: Vec<String>,
 }
 
 #[instrument(skip(client))]
 pub async fn on_install(client: &Client, opt: &AptOptions) -> anyhow::Result<()> {
-    let packages = opt.install.join(" ");
     run(client, "export DEBIAN_FRONTEND=noninteractive").await?;
     run(client, "sudo apt-get update 2>&1").await?;
-    if let Err(e) = run(
-        client,
-        &format!("sudo apt-get install -yq {} 2>&1", packages),
-    )
-    .await
-    {
+    let cmd = Cmd::new("sudo apt-get install -yq")
+        .operands()
+        .args(&opt.install)
+        .raw("2>&1");
+    if let Err(e) = run(client, &cmd).await {
         // parse each "Unable to locate package" row to return better error
         let mut missing = vec![];
         for line in e.to_string().lines() {
@@ -26,7 +28,7 @@ pub async fn on_install(client: &Client, opt: &AptOptions) -> anyhow::Result<()>
                 missing.push(package);
             }
         }
-        if missing.len() > 0 {
+        if !missing.is_empty() {
             bail!("Unable to locate: {}", missing.join(", "));
         }
         return Err(e);
@@ -35,29 +37,34 @@ pub async fn on_install(client: &Client, opt: &AptOptions) -> anyhow::Result<()>
     Ok(())
 }
 
-#[instrument(skip(client))]
-pub async fn on_check(client: &Client, opt: &AptOptions) -> anyhow::Result<Status> {
+#[instrument(skip(client, ctx))]
+pub async fn on_check(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &AptOptions,
+) -> anyhow::Result<Status> {
     let mut success = vec![];
     let mut fail = vec![];
-    for package in &opt.install {
-        let cmd = format!("sudo dpkg -s {} 2>&1", package);
-        match silent(client, &cmd).await {
-            Ok(output) => {
-                if output.exit_status == 0 {
-                    if output.output.contains("Status: install ok installed") {
-                        success.push(format!("{} ok", package));
-                    } else {
-                        fail.push(format!("{} missing", package));
-                    }
-                } else {
-                    let errmsg = output.output.replace("dpkg-query: ", "").clone();
-                    let first_line = errmsg.lines().next().unwrap_or("").to_string();
-                    fail.push(first_line);
-                }
-            }
-            Err(_) => {
+    let cmds = opt
+        .install
+        .iter()
+        .map(|package| Cmd::new("sudo dpkg -s").operands().arg(package).raw("2>&1"))
+        .collect::<Vec<_>>();
+    for (package, output) in opt
+        .install
+        .iter()
+        .zip(probe(client, ctx.probing, &cmds).await?)
+    {
+        if output.exit_status == 0 {
+            if output.output.contains("Status: install ok installed") {
+                success.push(format!("{} ok", package));
+            } else {
                 fail.push(format!("{} missing", package));
             }
+        } else {
+            let errmsg = output.output.replace("dpkg-query: ", "").clone();
+            let first_line = errmsg.lines().next().unwrap_or("").to_string();
+            fail.push(first_line);
         }
     }
     Ok(Status::new(success, fail))
@@ -1,6 +1,9 @@
 use crate::prelude::*;
+use crate::remote;
 
-#[derive(Debug, Default, Deserialize)]
+/// install AWS CLI v2 and upload a local profile with its keys and region
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct AwsOptions {
     /// profile to be uploaded
     pub profile: Option<String>,
@@ -17,7 +20,7 @@ fn read_aws_profile_region(profile: &str) -> anyhow::Result<String> {
     let mut found = false;
     let mut reading_profile = false;
     for line in aws_config.lines() {
-        if line.trim().len() == 0 || line.trim().starts_with('#') {
+        if line.trim().is_empty() || line.trim().starts_with('#') {
             // skip empty lines
             continue;
         }
@@ -61,7 +64,7 @@ fn read_aws_profile_keys(profile: &str) -> anyhow::Result<(String, String)> {
     let mut found = false;
     let mut reading_profile = false;
     for line in aws_config.lines() {
-        if line.trim().len() == 0 || line.trim().starts_with('#') {
+        if line.trim().is_empty() || line.trim().starts_with('#') {
             // skip empty lines
             continue;
         }
@@ -100,70 +103,72 @@ fn read_aws_profile_keys(profile: &str) -> anyhow::Result<(String, String)> {
 #[instrument(skip(client))]
 pub async fn on_install(client: &Client, opt: &AwsOptions) -> anyhow::Result<()> {
     // install aws2 CLI
-    if let Err(_) = which(client, "aws --version 2>&1").await {
+    if which(client, "aws --version 2>&1").await.is_err() {
         let cmd =
             "curl https://awscli.amazonaws.com/awscli-exe-linux-x86_64.zip -o awscliv2.zip 2>&1";
-        run(&client, cmd).await?;
+        run(client, cmd).await?;
 
         let cmd = "unzip -qo awscliv2.zip 2>&1";
-        run(&client, cmd).await?;
+        run(client, cmd).await?;
 
         let cmd = "sudo ./aws/install 2>&1";
-        run(&client, cmd).await?;
+        run(client, cmd).await?;
 
         let cmd = "rm -rf awscliv2.zip ./aws 2>&1";
-        run(&client, cmd).await?;
+        run(client, cmd).await?;
     }
     // set up aws profile
     if let Some(p) = &opt.profile {
         let profile = opt.rename.as_ref().unwrap_or(p);
 
-        let (access_key, secret_key) = read_aws_profile_keys(&p)?;
-        let region = read_aws_profile_region(&p)?;
-        let cmd = format!(
-            "aws configure set aws_access_key_id {} --profile {} 2>&1",
-            access_key, profile
-        );
-        run(&client, &cmd).await?;
-        let cmd = format!(
-            "aws configure set aws_secret_access_key {} --profile {} 2>&1",
-            secret_key, profile
-        );
-        run(&client, &cmd).await?;
-        let cmd = format!(
-            "aws configure set region {} --profile {} 2>&1",
-            region, profile
-        );
-        run(&client, &cmd).await?;
+        let (access_key, secret_key) = read_aws_profile_keys(p)?;
+        let region = read_aws_profile_region(p)?;
+        let settings = [
+            ("aws_access_key_id", access_key),
+            ("aws_secret_access_key", secret_key),
+            ("region", region),
+        ];
+        for (key, value) in settings {
+            let cmd = Cmd::new("aws configure set")
+                .arg(key)
+                .arg(value)
+                .raw("--profile")
+                .arg(profile)
+                .raw("2>&1");
+            run(client, &cmd).await?;
+        }
     }
 
     Ok(())
 }
 
-#[instrument(skip(client))]
-pub async fn on_check(client: &Client, opt: &AwsOptions) -> anyhow::Result<Status> {
+#[instrument(skip(client, ctx))]
+pub async fn on_check(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &AwsOptions,
+) -> anyhow::Result<Status> {
     let mut success = vec![];
     let mut fail = vec![];
-    match which(client, "aws --version 2>&1").await {
+    let profile = opt
+        .profile
+        .as_ref()
+        .map(|p| opt.rename.as_ref().unwrap_or(p));
+    let mut cmds = vec![Cmd::new("aws --version 2>&1")];
+    if let Some(profile) = profile {
+        cmds.push(Cmd::new("aws configure --profile").arg(profile).raw("list"));
+    }
+    let out = probe(client, ctx.probing, &cmds).await?;
+
+    match found(&out[0]) {
         Ok(res) => success.push(res),
         Err(res) => fail.push(res.to_string()),
     };
-
-    if let Some(p) = &opt.profile {
-        let profile = opt.rename.as_ref().unwrap_or(p);
-
-        let cmd = format!("aws configure --profile {} list", profile);
-        match silent(&client, &cmd).await {
-            Ok(output) => {
-                if output.exit_status == 0 {
-                    success.push(format!("profile {} ok", profile));
-                } else {
-                    fail.push(format!("profile {} missing", profile));
-                }
-            }
-            Err(_) => {
-                fail.push(format!("profile {} missing", profile));
-            }
+    if let Some(profile) = profile {
+        if out[1].exit_status == 0 {
+            success.push(format!("profile {} ok", profile));
+        } else {
+            fail.push(format!("profile {} missing", profile));
         }
     }
     Ok(Status::new(success, fail))
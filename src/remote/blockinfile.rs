@@ -0,0 +1,189 @@
+use crate::prelude::*;
+use crate::remote::{self, lineinfile::LineState};
+
+/// keep a block of lines between markers in a remote file
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct BlockinfileOptions {
+    /// remote file to be edited
+    pub path: String,
+    /// name of the block in its markers, must be unique within the file
+    pub name: String,
+    /// contents of the block between the markers
+    #[serde(default)]
+    pub block: String,
+    /// whether the block should be `present` (default) or `absent`
+    #[serde(default)]
+    pub state: LineState,
+    /// comment prefix of the markers, `#` by default
+    pub comment: Option<String>,
+    /// keep a timestamped copy of the file before changing it
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub backup: bool,
+    /// command to validate the new file before it is written, `%s` is the path to it
+    pub validate: Option<String>,
+    /// edit the file with sudo, enabled by default
+    #[serde(
+        default = "bool_true",
+        deserialize_with = "deserialize_bool_from_anything"
+    )]
+    pub sudo: bool,
+}
+
+impl BlockinfileOptions {
+    pub(crate) fn markers(&self) -> (String, String) {
+        let comment = self.comment.as_deref().unwrap_or("#");
+        (
+            format!("{} BEGIN remote-playbook {}", comment, self.name),
+            format!("{} END remote-playbook {}", comment, self.name),
+        )
+    }
+
+    /// new contents of the file with this block applied.
+    /// The block is replaced in place, or appended to the end of the file.
+    /// A BEGIN marker without its END is an error, appending another block
+    /// after it would add a block on every run
+    pub fn apply(&self, contents: &str) -> anyhow::Result<String> {
+        let (begin, end) = self.markers();
+        let lines = contents.lines().collect::<Vec<_>>();
+        let start = lines.iter().position(|x| x.trim_end() == begin);
+        let finish = start.and_then(|start| {
+            lines[start..]
+                .iter()
+                .position(|x| x.trim_end() == end)
+                .map(|x| start + x)
+        });
+        if start.is_some() && finish.is_none() {
+            bail!("unterminated block {} in {}", self.name, self.path);
+        }
+
+        let mut block = vec![];
+        if self.state == LineState::Present {
+            block.push(begin.as_str());
+            block.extend(self.block.lines());
+            block.push(end.as_str());
+        }
+
+        let mut result = match (start, finish) {
+            (Some(start), Some(finish)) => {
+                let mut result = lines[..start].to_vec();
+                result.extend(block);
+                result.extend(&lines[finish + 1..]);
+                result
+            }
+            _ => {
+                let mut result = lines.clone();
+                result.extend(block);
+                result
+            }
+        }
+        .join("\n");
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        Ok(result)
+    }
+}
+
+/// desired contents of the file, `None` if the file is already in that state
+async fn desired(
+    client: &Client,
+    opt: &BlockinfileOptions,
+) -> anyhow::Result<(Option<String>, Option<String>)> {
+    let actual = read_text(client, &opt.path, opt.sudo).await?;
+    let contents = opt.apply(actual.as_deref().unwrap_or(""))?;
+    if Some(&contents) == actual.as_ref() || (actual.is_none() && contents.is_empty()) {
+        return Ok((actual, None));
+    }
+    Ok((actual, Some(contents)))
+}
+
+#[instrument(skip(client, ctx))]
+pub async fn on_install(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &[BlockinfileOptions],
+) -> anyhow::Result<()> {
+    for item in opt {
+        if let (_, Some(contents)) = desired(client, item).await? {
+            // the whole file is rewritten, so it is confirmed on its own with `--step`
+            ctx.approve(&format!("rewrite {}", item.path))?;
+            replace_file(
+                client,
+                &item.path,
+                contents.as_bytes(),
+                item.sudo,
+                None,
+                item.backup,
+                item.validate.as_deref(),
+            )
+            .await?;
+            info!("{} updated", item.path);
+        }
+    }
+    Ok(())
+}
+
+#[instrument(skip(client))]
+pub async fn on_check(client: &Client, opt: &[BlockinfileOptions]) -> anyhow::Result<Status> {
+    let mut success = vec![];
+    let mut fail = vec![];
+    let mut diff = vec![];
+    for item in opt {
+        match desired(client, item).await? {
+            (_, None) => success.push(format!("{} {} ok", item.path, item.name)),
+            (actual, Some(contents)) => {
+                fail.push(format!("{} {} changed", item.path, item.name));
+                diff.push(unified_diff(&item.path, actual.as_deref(), &contents));
+            }
+        }
+    }
+    Ok(Status::new(success, fail).with_diff(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_replaces_the_marked_block() {
+        let mut opt = BlockinfileOptions {
+            name: "sshd".to_string(),
+            block: "ClientAliveInterval 60\nTCPKeepAlive yes".to_string(),
+            ..Default::default()
+        };
+        let added = opt.apply("Port 22\n").unwrap();
+        assert_eq!(
+            added,
+            "Port 22\n# BEGIN remote-playbook sshd\nClientAliveInterval 60\nTCPKeepAlive yes\n# END remote-playbook sshd\n"
+        );
+        assert_eq!(opt.apply(&added).unwrap(), added);
+
+        opt.block = "ClientAliveInterval 30".to_string();
+        let updated = opt.apply(&format!("{}UsePAM yes\n", added)).unwrap();
+        assert_eq!(
+            updated,
+            "Port 22\n# BEGIN remote-playbook sshd\nClientAliveInterval 30\n# END remote-playbook sshd\nUsePAM yes\n"
+        );
+
+        opt.state = LineState::Absent;
+        assert_eq!(opt.apply(&updated).unwrap(), "Port 22\nUsePAM yes\n");
+    }
+
+    #[test]
+    fn it_refuses_an_unterminated_block() {
+        let opt = BlockinfileOptions {
+            path: "/etc/ssh/sshd_config".to_string(),
+            name: "sshd".to_string(),
+            block: "TCPKeepAlive yes".to_string(),
+            ..Default::default()
+        };
+        let err = opt
+            .apply("Port 22\n# BEGIN remote-playbook sshd\nTCPKeepAlive no\n")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unterminated block sshd in /etc/ssh/sshd_config"
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use crate::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// upload local files and directories, unchanged files are skipped
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CopyOptions {
+    /// local file or directory, directories are uploaded recursively
+    pub src: String,
+    /// remote destination, if it ends with `/`, the file name of `src` is kept
+    pub dest: String,
+    /// owner of the uploaded files, i.e. `root` or `www-data`
+    pub owner: Option<String>,
+    /// group of the uploaded files, defaults to the primary group of the owner
+    pub group: Option<String>,
+    /// permissions of the uploaded files, i.e. `0644`
+    pub mode: Option<String>,
+    /// keep a timestamped copy of the remote file before overwriting it
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub backup: bool,
+    /// write files with sudo, enabled by default
+    #[serde(
+        default = "bool_true",
+        deserialize_with = "deserialize_bool_from_anything"
+    )]
+    pub sudo: bool,
+}
+
+impl CopyOptions {
+    fn local_src(&self) -> PathBuf {
+        PathBuf::from(crate::connect::tilde_with_context(
+            &self.src,
+            dirs::home_dir,
+        ))
+    }
+
+    /// remote path that is compared against the checksums:
+    /// the destination directory or the final file name
+    fn remote_root(&self) -> String {
+        let src = self.local_src();
+        if src.is_dir() {
+            self.dest.trim_end_matches('/').to_string()
+        } else {
+            self.remote_file(&src)
+        }
+    }
+
+    fn remote_file(&self, src: &Path) -> String {
+        if self.dest.ends_with('/') {
+            let name = src.file_name().unwrap_or_default().to_string_lossy();
+            format!("{}{}", self.dest, name)
+        } else {
+            self.dest.clone()
+        }
+    }
+
+    /// list of local files with their remote destinations
+    pub fn files(&self) -> anyhow::Result<Vec<(PathBuf, String)>> {
+        let src = self.local_src();
+        if !src.exists() {
+            bail!("{} not found", self.src);
+        }
+        if !src.is_dir() {
+            return Ok(vec![(src.clone(), self.remote_file(&src))]);
+        }
+        let mut result = vec![];
+        walk(&src, &mut result)?;
+        let root = self.dest.trim_end_matches('/');
+        Ok(result
+            .into_iter()
+            .map(|local| {
+                let relative = local.strip_prefix(&src).unwrap_or(&local);
+                let remote = format!("{}/{}", root, relative.to_string_lossy());
+                (local, remote)
+            })
+            .collect())
+    }
+
+    /// remote folders of a directory tree below the destination, that hold the files
+    fn folders(&self, files: &[(PathBuf, String)]) -> Vec<String> {
+        let root = self.dest.trim_end_matches('/');
+        let mut folders = std::collections::BTreeSet::new();
+        for (_, remote) in files {
+            let mut path = Path::new(remote.as_str());
+            while let Some(parent) = path.parent() {
+                let parent_str = parent.to_string_lossy();
+                if !parent_str.starts_with(root) || parent_str.len() <= root.len() {
+                    break;
+                }
+                folders.insert(parent_str.to_string());
+                path = parent;
+            }
+        }
+        folders.into_iter().collect()
+    }
+}
+
+/// files under the directory, symlinks to directories are not followed,
+/// so a link back to a parent can't loop forever
+fn walk(dir: &Path, result: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .context(format!("read {}", dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+    for path in entries {
+        let is_link = path.symlink_metadata()?.file_type().is_symlink();
+        if path.is_dir() && is_link {
+            warn!("{} is a symlink to a directory, skipped", path.display());
+        } else if path.is_dir() {
+            walk(&path, result)?;
+        } else {
+            result.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn owner_spec(opt: &CopyOptions) -> Option<String> {
+    match (&opt.owner, &opt.group) {
+        (Some(owner), Some(group)) => Some(format!("{}:{}", owner, group)),
+        (Some(owner), None) => Some(format!("{}:", owner)),
+        (None, Some(group)) => Some(format!(":{}", group)),
+        (None, None) => None,
+    }
+}
+
+#[instrument(skip(client))]
+async fn upload_one(client: &Client, opt: &CopyOptions) -> anyhow::Result<Vec<String>> {
+    let files = opt.files()?;
+    let remote = remote_checksums(client, &opt.remote_root(), opt.sudo).await?;
+
+    let mut changed = vec![];
+    for (local, dest) in &files {
+        let contents = std::fs::read(local).context(format!("read {}", local.display()))?;
+        if remote.get(dest) == Some(&sha256_hex(&contents)) {
+            debug!("{} is up to date", dest);
+            continue;
+        }
+        if opt.backup && remote.contains_key(dest) {
+            backup_file(client, dest, opt.sudo).await?;
+        }
+        upload(client, &contents, dest, opt.sudo, opt.mode.as_deref()).await?;
+        changed.push(dest.clone());
+    }
+
+    if files.is_empty() {
+        return Ok(changed);
+    }
+    let all = files.iter().map(|(_, dest)| dest);
+    if let Some(owner) = owner_spec(opt) {
+        // only the uploaded files and the folders of the tree, other files
+        // in the destination folder keep their owner
+        let cmd = Cmd::sudo(opt.sudo, "chown")
+            .operands()
+            .arg(owner)
+            .paths(opt.folders(&files))
+            .paths(all.clone())
+            .raw("2>&1");
+        run(client, &cmd).await?;
+    }
+    if let Some(mode) = &opt.mode {
+        let cmd = Cmd::sudo(opt.sudo, "chmod")
+            .operands()
+            .arg(mode)
+            .paths(all)
+            .raw("2>&1");
+        run(client, &cmd).await?;
+    }
+    Ok(changed)
+}
+
+#[instrument(skip(client))]
+pub async fn on_install(client: &Client, opt: &[CopyOptions]) -> anyhow::Result<()> {
+    for item in opt {
+        let changed = upload_one(client, item).await?;
+        for file in changed {
+            info!("{} uploaded", file);
+        }
+    }
+    Ok(())
+}
+
+/// permissions as printed by `stat -c %a`
+fn normalize_mode(mode: &str) -> &str {
+    let trimmed = mode.trim_start_matches('0');
+    if trimmed.is_empty() {
+        "0"
+    } else {
+        trimmed
+    }
+}
+
+#[instrument(skip(client))]
+pub async fn on_check(client: &Client, opt: &[CopyOptions]) -> anyhow::Result<Status> {
+    let mut success = vec![];
+    let mut fail = vec![];
+    let mut diff = vec![];
+    for item in opt {
+        let files = item.files()?;
+        let remote = remote_checksums(client, &item.remote_root(), item.sudo).await?;
+        let mut present = vec![];
+        for (local, dest) in &files {
+            let contents = std::fs::read(local).context(format!("read {}", local.display()))?;
+            match remote.get(dest) {
+                Some(hash) if *hash == sha256_hex(&contents) => {
+                    success.push(format!("{} ok", dest));
+                    present.push(dest.as_str());
+                }
+                Some(_) => {
+                    fail.push(format!("{} changed", dest));
+                    present.push(dest.as_str());
+                    let actual = read_file(client, dest, item.sudo).await?;
+                    diff.push(file_diff(dest, actual.as_deref(), &contents));
+                }
+                None => {
+                    fail.push(format!("{} missing", dest));
+                    diff.push(file_diff(dest, None, &contents));
+                }
+            }
+        }
+
+        if present.is_empty()
+            || (item.owner.is_none() && item.group.is_none() && item.mode.is_none())
+        {
+            continue;
+        }
+        let cmd = Cmd::sudo(item.sudo, "stat -c '%a %U %G %n'").paths(&present);
+        let out = run(client, &cmd).await?;
+        for line in out.output.lines() {
+            let mut parts = line.splitn(4, ' ');
+            let (Some(mode), Some(owner), Some(group), Some(file)) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            if let Some(expected) = &item.mode {
+                if normalize_mode(expected) != mode {
+                    fail.push(format!("{} mode {} instead of {}", file, mode, expected));
+                }
+            }
+            if let Some(expected) = &item.owner {
+                if expected != owner {
+                    fail.push(format!("{} owner {} instead of {}", file, owner, expected));
+                }
+            }
+            if let Some(expected) = &item.group {
+                if expected != group {
+                    fail.push(format!("{} group {} instead of {}", file, group, expected));
+                }
+            }
+        }
+    }
+    Ok(Status::new(success, fail).with_diff(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_lists_the_tree_without_following_links() {
+        let dir = std::env::temp_dir().join(format!("remote-playbook-copy-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("site/conf")).unwrap();
+        std::fs::write(dir.join("site/index.html"), "hello").unwrap();
+        std::fs::write(dir.join("site/conf/app.conf"), "listen 80;").unwrap();
+        std::os::unix::fs::symlink(dir.join("site"), dir.join("site/conf/loop")).unwrap();
+
+        let opt = CopyOptions {
+            src: dir.join("site").display().to_string(),
+            dest: "/etc/nginx/sites-enabled/".to_string(),
+            ..Default::default()
+        };
+        let files = opt.files().unwrap();
+        let remote = files.iter().map(|(_, x)| x.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            remote,
+            [
+                "/etc/nginx/sites-enabled/conf/app.conf",
+                "/etc/nginx/sites-enabled/index.html"
+            ]
+        );
+        assert_eq!(opt.folders(&files), ["/etc/nginx/sites-enabled/conf"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
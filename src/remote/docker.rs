@@ -1,7 +1,11 @@
 use crate::prelude::*;
+use crate::remote;
 
-#[derive(Debug, Default, Deserialize)]
+/// install docker from its apt repository and add the SSH user to the `docker` group
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct DockerOptions {
+    /// data root of the docker daemon, i.e. `/data`
     pub path: Option<String>,
 }
 
@@ -22,6 +26,17 @@ impl DockerConfig {
     }
 }
 
+/// daemon.json, that is written when the host has none yet
+fn daemon_config(path: &str) -> anyhow::Result<String> {
+    Ok(serde_json::to_string_pretty(&DockerConfig::new(path))? + "\n")
+}
+
+/// whether both are the same JSON, regardless of formatting
+fn same_json(actual: &str, desired: &str) -> bool {
+    let parse = |x: &str| serde_json::from_str::<serde_json::Value>(x).ok();
+    parse(actual).is_some_and(|actual| Some(actual) == parse(desired))
+}
+
 const GPG_PATH: &str = "/etc/apt/keyrings/docker.gpg";
 const SOURCES_LIST_PATH: &str = "/etc/apt/sources.list.d/docker.list";
 const DAEMON_CONFIG_PATH: &str = "/etc/docker/daemon.json";
@@ -38,16 +53,8 @@ pub async fn on_install(client: &Client, opt: &DockerOptions) -> anyhow::Result<
         if file_exists(client, DAEMON_CONFIG_PATH).await {
             debug!("docker config already exists, skipping");
         } else {
-            // if not, overwrite it
-            let cmd1 = "sudo mkdir -p /etc/docker";
-            run(&client, &cmd1).await?;
-
-            let cmd = format!(
-                "sudo bash -c 'echo {:?} > {}'",
-                serde_json::to_string(&DockerConfig::new(&path))?,
-                DAEMON_CONFIG_PATH,
-            );
-            run(client, &cmd).await?;
+            let contents = daemon_config(path)?;
+            upload(client, contents.as_bytes(), DAEMON_CONFIG_PATH, true, None).await?;
         }
     }
 
@@ -56,7 +63,10 @@ pub async fn on_install(client: &Client, opt: &DockerOptions) -> anyhow::Result<
         run(client, "sudo mkdir -m 0755 -p /etc/apt/keyrings").await?;
 
         let origin = format!("https://download.docker.com/linux/{}/gpg", os_str);
-        let cmd = format!("curl -fsSL {} | sudo gpg --dearmor -o {}", origin, GPG_PATH);
+        let cmd = Cmd::new("curl -fsSL")
+            .arg(origin)
+            .raw("| sudo gpg --dearmor -o")
+            .path(GPG_PATH);
         run(client, &cmd).await?;
         // chmod a+r /etc/apt/keyrings/docker.gpg ?
     }
@@ -82,42 +92,97 @@ pub async fn on_install(client: &Client, opt: &DockerOptions) -> anyhow::Result<
         ),
     };
     if !packages.is_empty() {
-        let cmd = format!("sudo apt-get install -y {} 2>&1", packages.join(" "));
+        let cmd = Cmd::new("sudo apt-get install -y")
+            .raw(&packages.join(" "))
+            .raw("2>&1");
         run(client, &cmd).await?;
     }
     silent(client, "sudo usermod -aG docker $USER 2>&1").await?;
     Ok(())
 }
 
-#[instrument(skip(client))]
-pub async fn on_check(client: &Client, opt: &DockerOptions) -> anyhow::Result<Status> {
+#[instrument(skip(client, ctx))]
+pub async fn on_check(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &DockerOptions,
+) -> anyhow::Result<Status> {
     let mut success = vec![];
     let mut fail = vec![];
 
-    if let Os::Unsupported = osinfo(client).await {
+    let cmds = [
+        Cmd::new("uname -a"),
+        Cmd::new("docker --version 2>&1"),
+        Cmd::new("ls -1").path(GPG_PATH),
+        Cmd::new("ls -1").path(SOURCES_LIST_PATH),
+        Cmd::new("cat /etc/group | grep docker | grep $USER"),
+    ];
+    let out = probe(client, ctx.probing, &cmds).await?;
+    if let Os::Unsupported = Os::from_uname(&out[0].output) {
         bail!("unsupported OS");
     };
 
-    match which(client, "docker --version 2>&1").await {
+    match found(&out[1]) {
         Ok(res) => success.push(res),
         Err(res) => fail.push(res.to_string()),
     };
 
-    if file_exists(client, GPG_PATH).await {
+    let mut diff = vec![];
+    if let Some(path) = &opt.path {
+        // an existing daemon.json is left as it is by install,
+        // its differences from the desired one are shown to spot manual edits
+        let desired = daemon_config(path)?;
+        match read_text(client, DAEMON_CONFIG_PATH, true).await? {
+            Some(actual) if same_json(&actual, &desired) => {
+                success.push("daemon config ok".to_string())
+            }
+            Some(actual) => {
+                fail.push(format!("{} differs", DAEMON_CONFIG_PATH));
+                diff.push(unified_diff(DAEMON_CONFIG_PATH, Some(&actual), &desired));
+            }
+            None => {
+                fail.push(format!("missing {}", DAEMON_CONFIG_PATH));
+                diff.push(unified_diff(DAEMON_CONFIG_PATH, None, &desired));
+            }
+        }
+    }
+
+    if out[2].exit_status == 0 {
         success.push("gpg key ok".to_string());
     } else {
         fail.push("missing gpg key".to_string());
     }
-    if file_exists(client, SOURCES_LIST_PATH).await {
+    if out[3].exit_status == 0 {
         success.push("sources list ok".to_string());
     } else {
         fail.push(format!("missing {}", SOURCES_LIST_PATH));
     }
-    if some_output(client, "cat /etc/group | grep docker | grep $USER").await {
+    if out[4].exit_status == 0 && !out[4].output.trim().is_empty() {
         success.push("user is in docker group".to_string());
     } else {
         fail.push("user is not in docker group".to_string());
     }
 
-    Ok(Status::new(success, fail))
+    Ok(Status::new(success, fail).with_diff(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_writes_the_daemon_config() {
+        let desired = daemon_config("/data/").unwrap();
+        let value: serde_json::Value = serde_json::from_str(&desired).unwrap();
+        assert_eq!(value["data-root"], "/data/docker");
+
+        let diff = unified_diff(DAEMON_CONFIG_PATH, None, &desired);
+        assert!(diff.starts_with("--- /dev/null\n+++ /etc/docker/daemon.json\n"));
+
+        let compact = r#"{"log-driver":"json-file","data-root":"/data/docker"}"#;
+        assert!(same_json(compact, &desired));
+        let edited = r#"{"data-root":"/var/lib/docker","log-driver":"json-file"}"#;
+        assert!(!same_json(edited, &desired));
+        assert!(!same_json("{", &desired));
+    }
 }
@@ -1,12 +1,14 @@
 use crate::prelude::*;
 
-#[derive(Debug, Default, Deserialize)]
+/// run prometheus exporter of docker stats on port 9487
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct DockerStatsOptions {}
 
 #[instrument(skip(client))]
 pub async fn on_install(client: &Client, opt: &DockerStatsOptions) -> anyhow::Result<()> {
     let cmd = "docker run -d --name=docker-stats --restart=always -p 9487:9487 -v /var/run/docker.sock:/var/run/docker.sock wywywywy/docker_stats_exporter:latest";
-    client.execute(cmd).await?;
+    silent(client, cmd).await?;
     Ok(())
 }
 
@@ -16,9 +18,11 @@ pub async fn on_check(client: &Client, opt: &DockerStatsOptions) -> anyhow::Resu
     let mut success = vec![];
     let mut fail = vec![];
 
-    let result = client
-        .execute("docker ps --filter=name=docker-stats --format '{{.ID}}'")
-        .await?;
+    let result = silent(
+        client,
+        "docker ps --filter=name=docker-stats --format '{{.ID}}'",
+    )
+    .await?;
     if result.output.is_empty() {
         fail.push("docker-stats is not running".to_string());
     } else {
@@ -0,0 +1,146 @@
+use crate::prelude::*;
+use crate::remote;
+use std::path::{Component, Path, PathBuf};
+
+/// download files from the host, checksums are validated
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FetchOptions {
+    /// remote file or directory, directories are downloaded recursively
+    pub src: String,
+    /// local destination, `{host}` is replaced with the name of the host.
+    /// If it ends with `/`, the file name of `src` is kept
+    pub dest: String,
+    /// read remote files with sudo, enabled by default
+    #[serde(
+        default = "bool_true",
+        deserialize_with = "deserialize_bool_from_anything"
+    )]
+    pub sudo: bool,
+}
+
+impl FetchOptions {
+    /// local path of the remote file, that was listed under `src`
+    fn local_path(&self, ctx: &remote::Context, remote_file: &str) -> anyhow::Result<PathBuf> {
+        let dest = self.dest.replace("{host}", &ctx.host);
+        let dest = crate::connect::tilde_with_context(&dest, dirs::home_dir);
+        let src = self.src.trim_end_matches('/');
+        if remote_file == src {
+            // single file
+            if dest.ends_with('/') {
+                let name = src.rsplit('/').next().unwrap_or(src);
+                Ok(PathBuf::from(dest).join(name))
+            } else {
+                Ok(PathBuf::from(dest))
+            }
+        } else {
+            let relative = Path::new(remote_file)
+                .strip_prefix(src)
+                .ok()
+                .filter(|x| x.components().all(|c| matches!(c, Component::Normal(_))))
+                .context(format!("{} is not a file under {}", remote_file, src))?;
+            Ok(PathBuf::from(dest).join(relative))
+        }
+    }
+
+    /// remote files with their checksums, fails if nothing was found
+    async fn remote_files(&self, client: &Client) -> anyhow::Result<Map<String, String>> {
+        let files = remote_checksums(client, self.src.trim_end_matches('/'), self.sudo).await?;
+        if files.is_empty() {
+            bail!("{} not found", self.src);
+        }
+        Ok(files)
+    }
+}
+
+fn local_checksum(path: &Path) -> Option<String> {
+    std::fs::read(path)
+        .ok()
+        .map(|contents| sha256_hex(&contents))
+}
+
+#[instrument(skip(client, ctx))]
+pub async fn on_install(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &[FetchOptions],
+) -> anyhow::Result<()> {
+    for item in opt {
+        for (file, hash) in item.remote_files(client).await? {
+            let local = item.local_path(ctx, &file)?;
+            if local_checksum(&local).as_ref() == Some(&hash) {
+                debug!("{} is up to date", local.display());
+                continue;
+            }
+            let contents = download(client, &file, item.sudo, &hash).await?;
+            if let Some(parent) = local.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&local, contents).context(format!("write {}", local.display()))?;
+            info!("{} downloaded into {}", file, local.display());
+        }
+    }
+    Ok(())
+}
+
+#[instrument(skip(client, ctx))]
+pub async fn on_check(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &[FetchOptions],
+) -> anyhow::Result<Status> {
+    let mut success = vec![];
+    let mut fail = vec![];
+    for item in opt {
+        let files = match item.remote_files(client).await {
+            Ok(files) => files,
+            Err(e) => {
+                fail.push(e.to_string());
+                continue;
+            }
+        };
+        for (file, hash) in files {
+            let local = match item.local_path(ctx, &file) {
+                Ok(local) => local,
+                Err(e) => {
+                    fail.push(e.to_string());
+                    continue;
+                }
+            };
+            match local_checksum(&local) {
+                Some(local_hash) if local_hash == hash => {
+                    success.push(format!("{} ok", local.display()))
+                }
+                Some(_) => fail.push(format!("{} outdated", local.display())),
+                None => fail.push(format!("{} missing", local.display())),
+            }
+        }
+    }
+    Ok(Status::new(success, fail))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_maps_remote_files_to_local_paths() {
+        let ctx = remote::Context {
+            host: "web1".to_string(),
+            ..Default::default()
+        };
+        let opt = FetchOptions {
+            src: "/var/log/nginx/".to_string(),
+            dest: "/backup/{host}/".to_string(),
+            ..Default::default()
+        };
+        let local = opt
+            .local_path(&ctx, "/var/log/nginx/old/access.log")
+            .unwrap();
+        assert_eq!(local, PathBuf::from("/backup/web1/old/access.log"));
+        assert!(opt.local_path(&ctx, "/var/log/nginx.log").is_err());
+        assert!(opt
+            .local_path(&ctx, "/var/log/nginx/../../etc/passwd")
+            .is_err());
+    }
+}
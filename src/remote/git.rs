@@ -1,13 +1,28 @@
 use crate::prelude::*;
 
-#[derive(Debug, Default, Deserialize)]
+/// clone a repository, if it is not cloned yet
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct GitOptions {
+    /// remote folder of the clone
     pub to: String,
+    /// URL of the repository
     pub clone: String,
 }
 
 use std::path::Path;
 
+/// `git clone` of the URL, that can't be taken as an option like `--upload-pack`
+fn clone(url: &str, dest: &str) -> Cmd {
+    let ssh_opts = "ssh -o UserKnownHostsFile=/dev/null -o StrictHostKeyChecking=no";
+    Cmd::new(&format!("GIT_SSH_COMMAND={}", quote(ssh_opts)))
+        .raw("GIT_TERMINAL_PROMPT=0 git clone")
+        .operands()
+        .arg(url)
+        .path(dest)
+        .raw("2>&1")
+}
+
 #[instrument(skip(client))]
 pub async fn on_install(client: &Client, opt: &GitOptions) -> anyhow::Result<()> {
     // syncing each local key with the remote location
@@ -30,18 +45,14 @@ pub async fn on_install(client: &Client, opt: &GitOptions) -> anyhow::Result<()>
             None => "".to_string(),
         };
         if parent != home_dir {
-            run(&client, &format!("mkdir -p {} 2>&1", parent)).await?;
+            let cmd = Cmd::new("mkdir -p").operands().path(parent).raw("2>&1");
+            run(client, &cmd).await?;
         } else {
             // we are cloning to home dir, so we need to use relative path
             dest = base_name.to_string();
         }
 
-        let ssh_opts = "ssh -o UserKnownHostsFile=/dev/null -o StrictHostKeyChecking=no";
-        let cmd = format!(
-            "GIT_SSH_COMMAND=\"{}\" GIT_TERMINAL_PROMPT=0 git clone {} {} 2>&1",
-            ssh_opts, opt.clone, dest,
-        );
-        run(&client, &cmd).await?;
+        run(client, &clone(&opt.clone, &dest)).await?;
     }
 
     Ok(())
@@ -52,16 +63,24 @@ pub async fn on_check(client: &Client, opt: &GitOptions) -> anyhow::Result<Statu
     let mut success = vec![];
     let mut fail = vec![];
 
-    if file_exists(client, &opt.to).await {
-        let git_config = format!("{}/.git/config", opt.to);
-        if file_exists(client, &git_config).await {
-            success.push(format!("{} ok", opt.to));
-        } else {
-            fail.push(format!("{} missing", opt.to));
-        }
+    // a single round-trip: the config is missing, if the folder is missing
+    let git_config = format!("{}/.git/config", opt.to);
+    if file_exists(client, &git_config).await {
+        success.push(format!("{} ok", opt.to));
     } else {
         fail.push(format!("{} missing", opt.to));
     }
 
     Ok(Status::new(success, fail))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_does_not_take_the_url_as_an_option() {
+        let cmd = clone("--upload-pack=touch /tmp/pwned", "/data/repo");
+        assert!(cmd.ends_with(" git clone -- '--upload-pack=touch /tmp/pwned' /data/repo 2>&1"));
+    }
+}
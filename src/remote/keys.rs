@@ -1,27 +1,31 @@
 use crate::prelude::*;
 
-#[derive(Debug, Default, Deserialize)]
+/// upload local SSH keys to the same paths on the host
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct KeysOptions {
+    /// key files, i.e. `~/.ssh/id_rsa`
     pub sync: Vec<String>,
+    /// permissions of the uploaded keys, i.e. `0600`
     pub perm: Option<String>,
 }
 
 #[instrument(skip(client))]
 pub async fn on_install(client: &Client, opt: &KeysOptions) -> anyhow::Result<()> {
     // syncing each local key with the remote location
-    // such approach works only for small base64-encoded key files
     for file in &opt.sync {
-        let local_path = crate::connect::tilde_with_context(&file, dirs::home_dir);
-        let contents = std::fs::read_to_string(&local_path)?;
+        let local_path = crate::connect::tilde_with_context(file, dirs::home_dir);
+        let contents = std::fs::read(&local_path)?;
 
-        if !file_exists(client, &file).await {
-            let cmd = format!("echo '{}' > {}", contents, file);
-            run(&client, &cmd).await?;
+        if !file_exists(client, file).await {
+            // keys are created private, never with the default mode of the umask
+            let mode = opt.perm.as_deref().unwrap_or("0600");
+            upload(client, &contents, file, false, Some(mode)).await?;
         }
 
         if let Some(perm) = &opt.perm {
-            let cmd = format!("chmod {} {}", perm, file);
-            run(&client, &cmd).await?;
+            let cmd = Cmd::new("chmod").operands().arg(perm).path(file);
+            run(client, &cmd).await?;
         }
     }
     Ok(())
@@ -32,18 +36,14 @@ pub async fn on_check(client: &Client, opt: &KeysOptions) -> anyhow::Result<Stat
     let mut success = vec![];
     let mut fail = vec![];
     for file in &opt.sync {
-        let cmd = format!("ls -1 {}", file);
-        match silent(&client, &cmd).await {
-            Ok(output) => {
-                if output.exit_status == 0 {
-                    success.push(format!("{} found", file));
-                } else {
-                    fail.push(format!("{} missing", file));
-                }
-            }
-            Err(_) => {
-                fail.push(format!("{} missing", file));
-            }
+        let local_path = crate::connect::tilde_with_context(file, dirs::home_dir);
+        let contents = std::fs::read(&local_path)?;
+        // keys are secrets, so only their checksums are compared, never printed as a diff
+        let remote = remote_checksums(client, file, false).await?;
+        match remote.get(file) {
+            Some(hash) if *hash == sha256_hex(&contents) => success.push(format!("{} ok", file)),
+            Some(_) => fail.push(format!("{} changed", file)),
+            None => fail.push(format!("{} missing", file)),
         }
     }
     Ok(Status::new(success, fail))
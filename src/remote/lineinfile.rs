@@ -0,0 +1,184 @@
+use crate::prelude::*;
+use crate::remote;
+use regex::Regex;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LineState {
+    #[default]
+    Present,
+    Absent,
+}
+
+/// keep a line in a remote file, replacing the line that matches `regexp`
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LineinfileOptions {
+    /// remote file to be edited
+    pub path: String,
+    /// regular expression of the line to be replaced or removed
+    pub regexp: Option<String>,
+    /// the line that should be in the file, required when present
+    pub line: Option<String>,
+    /// whether the line should be `present` (default) or `absent`
+    #[serde(default)]
+    pub state: LineState,
+    /// keep a timestamped copy of the file before changing it
+    #[serde(default, deserialize_with = "deserialize_bool_from_anything")]
+    pub backup: bool,
+    /// command to validate the new file before it is written, `%s` is the path to it
+    pub validate: Option<String>,
+    /// edit the file with sudo, enabled by default
+    #[serde(
+        default = "bool_true",
+        deserialize_with = "deserialize_bool_from_anything"
+    )]
+    pub sudo: bool,
+}
+
+impl LineinfileOptions {
+    /// ensure the line for the device is in `/etc/fstab`, replacing the line
+    /// of any of its sources, i.e. `UUID=...` or a `/dev/sdb` written before
+    pub fn fstab(sources: &[&str], line: &str) -> Self {
+        let sources = sources
+            .iter()
+            .map(|x| regex::escape(x))
+            .collect::<Vec<_>>()
+            .join("|");
+        LineinfileOptions {
+            path: "/etc/fstab".to_string(),
+            regexp: Some(format!("^({})\\s", sources)),
+            line: Some(line.to_string()),
+            backup: true,
+            sudo: true,
+            ..Default::default()
+        }
+    }
+
+    /// new contents of the file with this line applied
+    pub fn apply(&self, contents: &str) -> anyhow::Result<String> {
+        let regexp = match &self.regexp {
+            Some(x) => Some(Regex::new(x).context(format!("invalid regexp {}", x))?),
+            None => None,
+        };
+        let matches = |line: &str| match &regexp {
+            Some(re) => re.is_match(line),
+            None => Some(line) == self.line.as_deref(),
+        };
+        let mut lines = contents.lines().map(|x| x.to_string()).collect::<Vec<_>>();
+
+        match self.state {
+            LineState::Absent => {
+                if regexp.is_none() && self.line.is_none() {
+                    bail!("either regexp or line is required");
+                }
+                lines.retain(|line| !matches(line));
+            }
+            LineState::Present => {
+                let line = self.line.as_ref().context("line is required")?;
+                // like sed, the last matching line is replaced
+                match lines.iter().rposition(|x| matches(x)) {
+                    Some(index) => lines[index] = line.clone(),
+                    None => {
+                        if !lines.iter().any(|x| x == line) {
+                            lines.push(line.clone())
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut result = lines.join("\n");
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        Ok(result)
+    }
+}
+
+/// desired contents of the file, `None` if the file is already in that state
+async fn desired(
+    client: &Client,
+    opt: &LineinfileOptions,
+) -> anyhow::Result<(Option<String>, Option<String>)> {
+    let actual = read_text(client, &opt.path, opt.sudo).await?;
+    let contents = opt.apply(actual.as_deref().unwrap_or(""))?;
+    if Some(&contents) == actual.as_ref() || (actual.is_none() && contents.is_empty()) {
+        return Ok((actual, None));
+    }
+    Ok((actual, Some(contents)))
+}
+
+#[instrument(skip(client, ctx))]
+pub async fn on_install(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &[LineinfileOptions],
+) -> anyhow::Result<()> {
+    for item in opt {
+        if let (_, Some(contents)) = desired(client, item).await? {
+            // the whole file is rewritten, so it is confirmed on its own with `--step`
+            ctx.approve(&format!("rewrite {}", item.path))?;
+            replace_file(
+                client,
+                &item.path,
+                contents.as_bytes(),
+                item.sudo,
+                None,
+                item.backup,
+                item.validate.as_deref(),
+            )
+            .await?;
+            info!("{} updated", item.path);
+        }
+    }
+    Ok(())
+}
+
+#[instrument(skip(client))]
+pub async fn on_check(client: &Client, opt: &[LineinfileOptions]) -> anyhow::Result<Status> {
+    let mut success = vec![];
+    let mut fail = vec![];
+    let mut diff = vec![];
+    for item in opt {
+        match desired(client, item).await? {
+            (_, None) => success.push(format!("{} ok", item.path)),
+            (actual, Some(contents)) => {
+                fail.push(format!("{} changed", item.path));
+                diff.push(unified_diff(&item.path, actual.as_deref(), &contents));
+            }
+        }
+    }
+    Ok(Status::new(success, fail).with_diff(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_replaces_adds_and_removes_lines() {
+        let fstab = "UUID=1 / ext4 defaults 0 1\n/dev/sdc /old ext4 defaults 0 0\n";
+        let opt =
+            LineinfileOptions::fstab(&["/dev/sdc"], "/dev/sdc /data ext4 defaults,nofail 0 0");
+        let updated = opt.apply(fstab).unwrap();
+        assert_eq!(
+            updated,
+            "UUID=1 / ext4 defaults 0 1\n/dev/sdc /data ext4 defaults,nofail 0 0\n"
+        );
+        assert_eq!(opt.apply(&updated).unwrap(), updated);
+
+        let opt = LineinfileOptions::fstab(&["/dev/sdd"], "/dev/sdd /logs ext4 defaults 0 0");
+        assert!(opt
+            .apply(fstab)
+            .unwrap()
+            .ends_with("/dev/sdd /logs ext4 defaults 0 0\n"));
+
+        let opt = LineinfileOptions {
+            regexp: Some("^/dev/sdc".to_string()),
+            state: LineState::Absent,
+            ..Default::default()
+        };
+        assert_eq!(opt.apply(fstab).unwrap(), "UUID=1 / ext4 defaults 0 1\n");
+    }
+}
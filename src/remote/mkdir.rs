@@ -1,10 +1,16 @@
 use crate::prelude::*;
+use crate::remote;
 
-#[derive(Debug, Default, Deserialize)]
+/// create folders
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct MkdirOptions {
+    /// create folders with sudo
     #[serde(deserialize_with = "deserialize_bool_from_anything")]
     pub sudo: bool,
+    /// remote folders
     pub folders: Vec<String>,
+    /// permissions of the folders, i.e. `0777`
     pub perm: Option<String>,
 }
 
@@ -20,35 +26,43 @@ impl MkdirOptions {
 }
 #[instrument(skip(client))]
 pub async fn on_install(client: &Client, opt: &MkdirOptions) -> anyhow::Result<()> {
-    let sudo_prefix = if opt.sudo { "sudo " } else { "" };
-    let list = opt.folders.join(" ");
-    let cmd1 = format!("{} mkdir -p {}", sudo_prefix, list);
-    run(&client, &cmd1).await?;
+    let cmd1 = Cmd::sudo(opt.sudo, "mkdir -p")
+        .operands()
+        .paths(&opt.folders);
+    run(client, &cmd1).await?;
 
     let default = "0777".to_string();
     let perm = opt.perm.as_ref().unwrap_or(&default);
-    let cmd2 = format!("{} chmod -R {} {}", sudo_prefix, perm, list);
-    run(&client, &cmd2).await?;
+    let cmd2 = Cmd::sudo(opt.sudo, "chmod -R")
+        .operands()
+        .arg(perm)
+        .paths(&opt.folders);
+    run(client, &cmd2).await?;
     Ok(())
 }
 
-#[instrument(skip(client))]
-pub async fn on_check(client: &Client, opt: &MkdirOptions) -> anyhow::Result<Status> {
+#[instrument(skip(client, ctx))]
+pub async fn on_check(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &MkdirOptions,
+) -> anyhow::Result<Status> {
     let mut success = vec![];
     let mut fail = vec![];
-    for folder in &opt.folders {
-        let cmd = format!("ls -d {}", folder);
-        match silent(&client, &cmd).await {
-            Ok(output) => {
-                if output.exit_status == 0 {
-                    success.push(format!("{} found", folder));
-                } else {
-                    fail.push(format!("{} missing", folder));
-                }
-            }
-            Err(_) => {
-                fail.push(format!("{} missing", folder));
-            }
+    let cmds = opt
+        .folders
+        .iter()
+        .map(|folder| Cmd::new("ls -d").operands().path(folder))
+        .collect::<Vec<_>>();
+    for (folder, output) in opt
+        .folders
+        .iter()
+        .zip(probe(client, ctx.probing, &cmds).await?)
+    {
+        if output.exit_status == 0 {
+            success.push(format!("{} found", folder));
+        } else {
+            fail.push(format!("{} missing", folder));
         }
     }
     Ok(Status::new(success, fail))
@@ -1,14 +1,147 @@
 use crate::prelude::*;
+use crate::remote::{self, LineinfileOptions};
 
-#[derive(Debug, Default, Deserialize)]
+/// format and mount a data disk
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct MountOptions {
     /// Destination folder to be mounted, i.e. /data
-    /// the biggest free device that is not mounted is selected
+    /// the biggest free device that is not mounted is selected,
+    /// unless the device is given by `device`, `by_id`, `serial` or `size`
     pub to: String,
+    /// device to be mounted, i.e. `/dev/sdb` or `/dev/disk/by-id/...`
+    pub device: Option<String>,
+    /// part of the name of the device in `/dev/disk/by-id`, i.e. `Amazon_Elastic_Block_Store_vol0123`
+    pub by_id: Option<String>,
+    /// serial number of the device, as `lsblk -o SERIAL` shows it
+    pub serial: Option<String>,
+    /// size of the device, as `lsblk` shows it, i.e. `500G`
+    pub size: Option<String>,
+    /// filesystem of the formatted device, `ext4` by default
+    #[serde(default)]
+    pub fs: Filesystem,
+    /// options of the mount and of its line in `/etc/fstab`, `defaults,nofail` by default
+    pub mount_options: Option<String>,
+    /// label of the new filesystem
+    pub label: Option<String>,
+    /// format the device, even if it has a filesystem or a partition table already
+    #[serde(default)]
+    pub force: bool,
 }
 impl MountOptions {
     pub fn new(to: &str) -> Self {
-        MountOptions { to: to.to_string() }
+        MountOptions {
+            to: to.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// matchers of the device, that are set
+    fn matchers(&self) -> Vec<String> {
+        [
+            ("device", &self.device),
+            ("by_id", &self.by_id),
+            ("serial", &self.serial),
+            ("size", &self.size),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_ref().map(|x| format!("{} = {:?}", name, x)))
+        .collect()
+    }
+
+    fn mount_options(&self) -> &str {
+        self.mount_options.as_deref().unwrap_or("defaults,nofail")
+    }
+
+    /// whether the device is the one given by the matchers
+    fn matches(&self, disks: &Disks, device: &BlockDevice) -> bool {
+        let name = &device.name;
+        disks.device.as_ref().is_none_or(|x| x == name)
+            && self.by_id.as_ref().is_none_or(|id| {
+                disks
+                    .ids
+                    .iter()
+                    .any(|(x, target)| target == name && x.contains(id.as_str()))
+            })
+            && self
+                .serial
+                .as_ref()
+                .is_none_or(|x| device.serial.as_ref() == Some(x))
+            && self
+                .size
+                .as_ref()
+                .is_none_or(|x| device.size.eq_ignore_ascii_case(x))
+    }
+
+    /// device for the folder and whether it is mounted already.
+    /// A device, that is not mounted yet, must be the only free one matching,
+    /// partitions are matched as well, i.e. `device = "/dev/sdb1"`
+    fn select<'a>(&self, disks: &'a Disks) -> anyhow::Result<(&'a BlockDevice, bool)> {
+        let devices = &disks.lsblk;
+        if let Some(device) = devices.get_mounted_to(self.to.clone()) {
+            if !self.matches(disks, device) {
+                bail!(
+                    "{} is mounted from {}, that does not match {}",
+                    self.to,
+                    device.name,
+                    self.matchers().join(", ")
+                );
+            }
+            return Ok((device, true));
+        }
+        if self.matchers().is_empty() {
+            let device = devices
+                .get_biggest_unmounted()
+                .context("failed to find target block device")?;
+            return Ok((device, false));
+        }
+        let found = devices
+            .all()
+            .into_iter()
+            .filter(|x| self.matches(disks, x))
+            .collect::<Vec<_>>();
+        match found.as_slice() {
+            [] => bail!("no device matches {}", self.matchers().join(", ")),
+            [device] if device.is_busy() => bail!("device {} is in use", device.name),
+            [device] => Ok((device, false)),
+            _ => bail!(
+                "devices {} match {}",
+                found
+                    .iter()
+                    .map(|x| x.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                self.matchers().join(", ")
+            ),
+        }
+    }
+}
+
+/// filesystem of the formatted device
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Filesystem {
+    #[default]
+    Ext4,
+    Xfs,
+    Btrfs,
+}
+
+impl Filesystem {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filesystem::Ext4 => "ext4",
+            Filesystem::Xfs => "xfs",
+            Filesystem::Btrfs => "btrfs",
+        }
+    }
+
+    /// flag of mkfs to format a device, that has a filesystem already
+    fn force_flag(&self) -> &'static str {
+        match self {
+            Filesystem::Ext4 => "-F",
+            Filesystem::Xfs | Filesystem::Btrfs => "-f",
+        }
     }
 }
 
@@ -25,6 +158,14 @@ pub struct BlockDevice {
     #[serde(rename = "type")]
     pub blocktype: String,
     pub mountpoints: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    /// UUID of the filesystem on the device
+    #[serde(default)]
+    pub uuid: Option<String>,
+    /// type of the filesystem on the device, i.e. `ext4`
+    #[serde(default)]
+    pub fstype: Option<String>,
     pub children: Option<Vec<BlockDevice>>,
 }
 
@@ -51,16 +192,16 @@ impl BlockDevice {
 
     fn has_children(&self) -> bool {
         match &self.children {
-            Some(children) => children.len() > 0,
+            Some(children) => !children.is_empty(),
             None => false,
         }
     }
 
     pub fn is_busy(&self) -> bool {
-        if self.blocktype != "disk" || self.has_children() {
+        if !["disk", "part"].contains(&self.blocktype.as_str()) || self.has_children() {
             return true;
         }
-        if self.mountpoints.len() == 0 {
+        if self.mountpoints.is_empty() {
             return false;
         }
         if self.mountpoints.len() == 1 {
@@ -69,7 +210,7 @@ impl BlockDevice {
                 return false;
             }
         }
-        return true;
+        true
     }
 }
 
@@ -79,8 +220,21 @@ struct LsBlkOutput {
 }
 
 impl LsBlkOutput {
+    /// disks with their partitions
+    fn all(&self) -> Vec<&BlockDevice> {
+        fn walk<'a>(devices: &'a [BlockDevice], out: &mut Vec<&'a BlockDevice>) {
+            for device in devices {
+                out.push(device);
+                walk(device.children.as_deref().unwrap_or_default(), out);
+            }
+        }
+        let mut out = vec![];
+        walk(&self.blockdevices, &mut out);
+        out
+    }
+
     pub fn get_mounted_to(&self, to: String) -> Option<&BlockDevice> {
-        for device in &self.blockdevices {
+        for device in self.all() {
             for mountpoint in &device.mountpoints {
                 if mountpoint.is_null() {
                     continue;
@@ -116,114 +270,336 @@ impl LsBlkOutput {
     }
 }
 
-#[instrument(skip(client))]
+/// block devices of the host
+struct Disks {
+    lsblk: LsBlkOutput,
+    /// names in `/dev/disk/by-id` with the names of their devices
+    ids: Vec<(String, String)>,
+    /// name of the device given by its path, i.e. `sdb`
+    device: Option<String>,
+    fstab: String,
+}
+
+const LSBLK: &str = "lsblk -J -o NAME,MAJ:MIN,RM,SIZE,RO,TYPE,MOUNTPOINTS,SERIAL,UUID,FSTYPE";
+
+/// `ls -l /dev/disk/by-id` as pairs of the link and the name of its device
+fn parse_ids(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (link, target) = line.split_once(" -> ")?;
+            let link = link.rsplit(' ').next()?;
+            let target = target.trim().rsplit('/').next()?;
+            Some((link.to_string(), target.to_string()))
+        })
+        .collect()
+}
+
+/// type of the filesystem or of the partition table, from `blkid -p -o export`
+fn signature(output: &str) -> Option<&str> {
+    output.lines().find_map(|line| {
+        line.strip_prefix("TYPE=")
+            .or_else(|| line.strip_prefix("PTTYPE="))
+    })
+}
+
+async fn disks(client: &Client, probing: Probing, opt: &MountOptions) -> anyhow::Result<Disks> {
+    let mut cmds = vec![
+        Cmd::new(LSBLK),
+        Cmd::new("cat /etc/fstab"),
+        Cmd::new("ls -l /dev/disk/by-id 2>/dev/null"),
+    ];
+    if let Some(device) = &opt.device {
+        cmds.push(Cmd::new("readlink -f").path(device));
+    }
+    let out = probe(client, probing, &cmds).await?;
+    let lsblk = serde_json::from_str::<LsBlkOutput>(&out[0].output).context("lsblk")?;
+    for x in &lsblk.blockdevices {
+        if !x.is_busy() {
+            debug!("{} {} is not busy", x.name, x.size);
+        }
+    }
+    let device = out.get(3).map(|x| {
+        let path = x.output.trim();
+        path.strip_prefix("/dev/").unwrap_or(path).to_string()
+    });
+    Ok(Disks {
+        lsblk,
+        ids: parse_ids(&out[2].output),
+        device,
+        fstab: found(&out[1])?,
+    })
+}
+
+/// filesystem signature of the device, that would be erased by formatting it
+async fn existing_signature(client: &Client, device: &str) -> anyhow::Result<Option<String>> {
+    let cmd = Cmd::new("sudo blkid -p -o export").path(device).raw("2>&1");
+    let out = silent(client, &cmd).await?;
+    // exit status 2 tells that nothing was found
+    Ok(match out.exit_status {
+        0 => Some(signature(&out.output).unwrap_or("unknown").to_string()),
+        2 => None,
+        status => bail!("blkid {}: {} (exit {})", device, out.output.trim(), status),
+    })
+}
+
+/// value of the field in the output of `blkid -p -o export`
+fn field<'a>(output: &'a str, key: &str) -> Option<&'a str> {
+    output
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix('='))
+        .filter(|x| !x.is_empty())
+}
+
+/// UUID and type of the filesystem on the device. They are taken from `lsblk`
+/// for a device that was mounted already, and read with `blkid` when `lsblk`
+/// does not show them, i.e. after the device was formatted
+async fn filesystem(
+    client: &Client,
+    device: &BlockDevice,
+    mounted: bool,
+) -> anyhow::Result<(String, String)> {
+    if let (true, Some(uuid), Some(fstype)) = (mounted, &device.uuid, &device.fstype) {
+        return Ok((uuid.clone(), fstype.clone()));
+    }
+    let path = format!("/dev/{}", device.name);
+    let cmd = Cmd::new("sudo blkid -p -o export").path(&path).raw("2>&1");
+    let out = run(client, &cmd).await?.output;
+    let uuid = field(&out, "UUID").context(format!("{} has no filesystem UUID", path))?;
+    let fstype = field(&out, "TYPE").context(format!("{} has no filesystem type", path))?;
+    Ok((uuid.to_string(), fstype.to_string()))
+}
+
+#[instrument(skip(client, ctx))]
 async fn mounting(
     client: &Client,
+    ctx: &remote::Context,
     name: &str,
-    to: &str,
-    blocktype: &str,
-    fs_type: &str,
+    opt: &MountOptions,
 ) -> anyhow::Result<()> {
+    let device = format!("/dev/{}", name);
+    if let Some(signature) = existing_signature(client, &device).await? {
+        if !opt.force {
+            bail!(
+                "{} has {} on it already, set `force = true` to format it",
+                device,
+                signature
+            );
+        }
+    }
+
     // mkfs -t ext4 /dev/sdc
-    let cmd_mkfs = format!("sudo mkfs -t {} /dev/{} 2>&1", fs_type, name);
-    run(&client, &cmd_mkfs).await?;
+    let mut cmd_mkfs = Cmd::new("sudo mkfs -t").arg(opt.fs.name());
+    if opt.force {
+        cmd_mkfs = cmd_mkfs.raw(opt.fs.force_flag());
+    }
+    if let Some(label) = &opt.label {
+        cmd_mkfs = cmd_mkfs.raw("-L").arg(label);
+    }
+    let cmd_mkfs = cmd_mkfs.path(&device).raw("2>&1");
+    // formatting erases the device, so it is confirmed on its own with `--step`
+    ctx.approve(&cmd_mkfs)?;
+    run(client, &cmd_mkfs).await?;
 
     // mkdir -p /data2
-    let cmd_mkdir = format!("sudo mkdir -p {} 2>&1", to);
-    run(&client, &cmd_mkdir).await?;
+    let cmd_mkdir = Cmd::new("sudo mkdir -p")
+        .operands()
+        .path(&opt.to)
+        .raw("2>&1");
+    run(client, &cmd_mkdir).await?;
 
-    // mount /dev/sdc /data2
-    let cmd_mount = format!("sudo mount /dev/{} {} 2>&1", name, to);
-    run(&client, &cmd_mount).await?;
+    // mount -o defaults,nofail /dev/sdc /data2
+    let cmd_mount = Cmd::new("sudo mount -o")
+        .arg(opt.mount_options())
+        .path(&device)
+        .path(&opt.to)
+        .raw("2>&1");
+    run(client, &cmd_mount).await?;
     Ok(())
 }
 
-#[instrument(skip(client))]
-async fn update_fstab(client: &Client, name: &str, to: &str, fs_type: &str) -> anyhow::Result<()> {
-    // cp /etc/fstab /etc/fstab.bak
-    let cmd_fstab_b = "sudo cp /etc/fstab /etc/fstab.bak";
-    run(&client, cmd_fstab_b).await?;
-
-    // echo "/dev/sdc       /data   ext4    defaults,nofail        0       0" >> /etc/fstab
-    let cmd_fstab = format!(
-        "sudo sh -c 'echo \"/dev/{}       {}   {}    defaults,nofail        0       0\" >> /etc/fstab'",
-        name, to, fs_type
+/// line of the device in /etc/fstab. It is found by the UUID of its filesystem,
+/// because names like `/dev/nvme1n1` can change between boots. A line with
+/// the name of the device, written by earlier versions, is replaced.
+/// The type is the one on the device, that may differ from `fs` of a device mounted before
+fn fstab(name: &str, uuid: &str, fstype: &str, opt: &MountOptions) -> LineinfileOptions {
+    let device = format!("/dev/{}", name);
+    let source = format!("UUID={}", uuid);
+    // UUID=6a1d...       /data   ext4    defaults,nofail        0       0
+    let line = format!(
+        "{}       {}   {}    {}        0       0",
+        source,
+        opt.to,
+        fstype,
+        opt.mount_options()
     );
-    run(&client, &cmd_fstab).await?;
-    Ok(())
+    LineinfileOptions::fstab(&[&source, &device], &line)
 }
 
-#[instrument(skip(client))]
-pub async fn on_install(client: &Client, opt: &MountOptions) -> anyhow::Result<()> {
-    let result = client.execute("lsblk -J").await?;
-    let devices: LsBlkOutput = serde_json::from_str::<LsBlkOutput>(&result.output)?;
-    for x in &devices.blockdevices {
-        if !x.is_busy() {
-            debug!("{} {} is not busy\n", x.name, x.size);
-        }
-    }
-    let found = match devices.get_mounted_to(opt.to.clone()) {
-        Some(device) => {
-            info!("folder {} is already used by {}\n", opt.to, device.name);
-            device
-        }
-        None => {
-            let found = devices
-                .get_biggest_unmounted()
-                .context("failed to find target block device")?;
-            mounting(client, &found.name, &opt.to, &found.blocktype, "ext4").await?;
-            found
-        }
-    };
-
-    // check if the device is already mounted in fstab, and update it
-    let device_name = format!("/dev/{}", found.name);
-    let is_in_fstab = run(&client, "cat /etc/fstab")
-        .await?
-        .output
-        .contains(&device_name);
-    if !is_in_fstab {
-        update_fstab(client, &found.name, &opt.to, "ext4").await?;
+#[instrument(skip(client, ctx))]
+pub async fn on_install(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &MountOptions,
+) -> anyhow::Result<()> {
+    let disks = disks(client, ctx.probing, opt).await?;
+    let (found, mounted) = opt.select(&disks)?;
+    if mounted {
+        info!("folder {} is already used by {}", opt.to, found.name);
     } else {
-        info!("{} is already in fstab", device_name);
+        mounting(client, ctx, &found.name, opt).await?;
     }
 
-    Ok(())
+    // the line is written only if it is missing or changed
+    let (uuid, fstype) = filesystem(client, found, mounted).await?;
+    let fstab = fstab(&found.name, &uuid, &fstype, opt);
+    crate::remote::lineinfile::on_install(client, ctx, &[fstab]).await
 }
 
 // return types: ready for install, installed
-#[instrument(skip(client))]
-pub async fn on_check(client: &Client, opt: &MountOptions) -> anyhow::Result<Status> {
+#[instrument(skip(client, ctx))]
+pub async fn on_check(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &MountOptions,
+) -> anyhow::Result<Status> {
     let mut success = vec![];
     let mut fail = vec![];
-    let result = client.execute("lsblk -J").await?;
-    let devices: LsBlkOutput = serde_json::from_str::<LsBlkOutput>(&result.output)?;
-    for x in &devices.blockdevices {
-        if !x.is_busy() {
-            info!("{} {} is not busy\n", x.name, x.size);
-        }
-    }
-    let device_name = match &devices.get_mounted_to(opt.to.clone()) {
-        Some(device) => {
+    let mut diff = vec![];
+    let disks = disks(client, ctx.probing, opt).await?;
+    let (device, (uuid, fstype)) = match opt.select(&disks) {
+        Ok((device, true)) => {
             success.push(format!("folder {} is used by {}", opt.to, device.name));
-            format!("/dev/{}", device.name)
+            (device, filesystem(client, device, true).await?)
+        }
+        Ok((device, false)) => {
+            let device_name = format!("/dev/{}", device.name);
+            match existing_signature(client, &device_name).await? {
+                Some(signature) if !opt.force => {
+                    fail.push(format!("{} has {} on it already", device_name, signature))
+                }
+                _ => success.push(format!("{} can be formatted", device_name)),
+            }
+            // the UUID is known once the device is formatted
+            (device, ("<new>".to_string(), opt.fs.name().to_string()))
         }
-        None => {
-            let Some(found) = devices.get_biggest_unmounted() else {
-                fail.push("failed to find target block device".to_string());
-                return Ok(Status::NotInstalled { success, fail });
-            };
-            format!("/dev/{}", found.name)
+        Err(e) => {
+            fail.push(e.to_string());
+            return Ok(Status::new(success, fail));
         }
     };
-    let is_in_fstab = run(&client, "cat /etc/fstab")
-        .await?
-        .output
-        .contains(&device_name);
-    if !is_in_fstab {
-        fail.push(format!("device {} is not in fstab", device_name));
+    let desired = fstab(&device.name, &uuid, &fstype, opt).apply(&disks.fstab)?;
+    if desired != disks.fstab {
+        fail.push(format!("device /dev/{} is not in fstab", device.name));
+        diff.push(unified_diff("/etc/fstab", Some(&disks.fstab), &desired));
     } else {
-        success.push(format!("device {} is in fstab", device_name));
+        success.push(format!("device /dev/{} is in fstab", device.name));
+    }
+    Ok(Status::new(success, fail).with_diff(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disks(device: Option<&str>) -> Disks {
+        let lsblk = r#"{"blockdevices": [
+            {"name":"nvme0n1", "maj:min":"259:0", "rm":false, "size":"8G", "ro":false, "type":"disk", "mountpoints":[null], "serial":"vol0a",
+             "children": [{"name":"nvme0n1p1", "maj:min":"259:1", "rm":false, "size":"6G", "ro":false, "type":"part", "mountpoints":["/"]},
+                          {"name":"nvme0n1p2", "maj:min":"259:4", "rm":false, "size":"2G", "ro":false, "type":"part", "mountpoints":[null]}]},
+            {"name":"nvme1n1", "maj:min":"259:2", "rm":false, "size":"500G", "ro":false, "type":"disk", "mountpoints":[null], "serial":"vol1b"},
+            {"name":"nvme2n1", "maj:min":"259:3", "rm":false, "size":"1T", "ro":false, "type":"disk", "mountpoints":[null], "serial":"vol2c"}
+        ]}"#;
+        let ids = "lrwxrwxrwx 1 root root 13 Oct 19 10:00 nvme-Amazon_Elastic_Block_Store_vol1b -> ../../nvme1n1\n\
+                   lrwxrwxrwx 1 root root 13 Oct 19 10:00 nvme-Amazon_Elastic_Block_Store_vol2c -> ../../nvme2n1\n";
+        Disks {
+            lsblk: serde_json::from_str(lsblk).unwrap(),
+            ids: parse_ids(ids),
+            device: device.map(|x| x.to_string()),
+            fstab: String::new(),
+        }
+    }
+
+    #[test]
+    fn it_selects_the_device() {
+        let select = |opt: &MountOptions, device| {
+            opt.select(&disks(device))
+                .map(|(x, mounted)| (x.name.clone(), mounted))
+                .map_err(|e| e.to_string())
+        };
+        let mut opt = MountOptions::new("/data");
+        assert_eq!(select(&opt, None), Ok(("nvme2n1".to_string(), false)));
+
+        opt.size = Some("500g".to_string());
+        assert_eq!(select(&opt, None), Ok(("nvme1n1".to_string(), false)));
+
+        opt.size = None;
+        opt.by_id = Some("vol2c".to_string());
+        assert_eq!(select(&opt, None), Ok(("nvme2n1".to_string(), false)));
+
+        opt.by_id = Some("Elastic_Block_Store".to_string());
+        assert_eq!(
+            select(&opt, None),
+            Err(r#"devices nvme1n1, nvme2n1 match by_id = "Elastic_Block_Store""#.to_string())
+        );
+
+        opt.by_id = None;
+        opt.serial = Some("vol0a".to_string());
+        assert_eq!(
+            select(&opt, None),
+            Err("device nvme0n1 is in use".to_string())
+        );
+
+        opt.serial = None;
+        opt.device = Some("/dev/sdx".to_string());
+        assert!(select(&opt, Some("sdx"))
+            .unwrap_err()
+            .starts_with("no device matches"));
+
+        opt.device = Some("/dev/nvme0n1p2".to_string());
+        assert_eq!(
+            select(&opt, Some("nvme0n1p2")),
+            Ok(("nvme0n1p2".to_string(), false))
+        );
+        opt.device = Some("/dev/nvme0n1p1".to_string());
+        opt.to = "/".to_string();
+        assert_eq!(
+            select(&opt, Some("nvme0n1p1")),
+            Ok(("nvme0n1p1".to_string(), true))
+        );
+    }
+
+    #[test]
+    fn it_writes_the_uuid_to_fstab() {
+        let opt = MountOptions::new("/data");
+        let fstab = fstab("nvme1n1", "6a1d", "ext4", &opt);
+        let line = "UUID=6a1d       /data   ext4    defaults,nofail        0       0\n";
+        let legacy = "LABEL=root / ext4 defaults 0 1\n/dev/nvme1n1       /data   ext4    defaults,nofail        0       0\n";
+        assert_eq!(
+            fstab.apply(legacy).unwrap(),
+            format!("LABEL=root / ext4 defaults 0 1\n{}", line)
+        );
+        assert_eq!(fstab.apply(line).unwrap(), line);
+
+        // a device mounted before keeps its filesystem, whatever `fs` is
+        let fstab = super::fstab("nvme1n1", "6a1d", "xfs", &opt);
+        assert_eq!(
+            fstab.apply(line).unwrap(),
+            "UUID=6a1d       /data   xfs    defaults,nofail        0       0\n"
+        );
+    }
+
+    #[test]
+    fn it_reads_signatures() {
+        assert_eq!(
+            signature("DEVNAME=/dev/sdb\nUUID=1\nTYPE=xfs\nUSAGE=filesystem\n"),
+            Some("xfs")
+        );
+        assert_eq!(signature("PTUUID=1\nPTTYPE=gpt\n"), Some("gpt"));
+        assert_eq!(signature(""), None);
+        let output = "DEVNAME=/dev/sdb\nUUID=6a1d\nBLOCK_SIZE=4096\nTYPE=xfs\n";
+        assert_eq!(field(output, "UUID"), Some("6a1d"));
+        assert_eq!(field(output, "TYPE"), Some("xfs"));
+        assert_eq!(field("PTUUID=1\nPTTYPE=gpt\n", "TYPE"), None);
     }
-    Ok(Status::new(success, fail))
 }
@@ -1,12 +1,14 @@
 use crate::prelude::*;
 
-#[derive(Debug, Default, Deserialize)]
+/// run prometheus node exporter on port 9100
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct NodeExporterOptions {}
 
 #[instrument(skip(client))]
 pub async fn on_install(client: &Client, opt: &NodeExporterOptions) -> anyhow::Result<()> {
     let cmd = "docker run --name node-exporter --restart=always -d --net=\"host\" --pid=\"host\" -v \"/:/host:ro,rslave\" quay.io/prometheus/node-exporter:latest --path.rootfs=/host";
-    client.execute(cmd).await?;
+    silent(client, cmd).await?;
     Ok(())
 }
 
@@ -16,9 +18,11 @@ pub async fn on_check(client: &Client, opt: &NodeExporterOptions) -> anyhow::Res
     let mut success = vec![];
     let mut fail = vec![];
 
-    let result = client
-        .execute("docker ps --filter=name=node-exporter --format '{{.ID}}'")
-        .await?;
+    let result = silent(
+        client,
+        "docker ps --filter=name=node-exporter --format '{{.ID}}'",
+    )
+    .await?;
     if result.output.is_empty() {
         fail.push("node-exporter is not running".to_string());
     } else {
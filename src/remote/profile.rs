@@ -0,0 +1,633 @@
+//! aliases and exports of the shell profile, kept in a block of the profile
+//! that is owned by the playbook, so changed values are rewritten
+//! and entries that are no longer declared are removed
+use crate::prelude::*;
+use crate::remote::{self, lineinfile::LineState, BlockinfileOptions};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Entry {
+    Alias,
+    Export,
+}
+
+impl Entry {
+    /// name of the section and of the block in the profile
+    pub fn section(&self) -> &'static str {
+        match self {
+            Entry::Alias => "aliases",
+            Entry::Export => "exports",
+        }
+    }
+}
+
+/// file, where the entries are declared
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileTarget {
+    /// `~/.bashrc`
+    #[default]
+    Bash,
+    /// `~/.zshrc`
+    Zsh,
+    /// `~/.config/fish/config.fish`
+    Fish,
+    /// `/etc/environment` for all users, exports only
+    #[serde(alias = "/etc/environment")]
+    Environment,
+    /// `/etc/profile.d/remote-playbook.sh` for login shells of all users
+    #[serde(rename = "profile.d", alias = "/etc/profile.d")]
+    ProfileD,
+}
+
+impl ProfileTarget {
+    /// whether the file is shared by all users
+    fn system(&self) -> bool {
+        matches!(self, ProfileTarget::Environment | ProfileTarget::ProfileD)
+    }
+
+    /// mode of a new file: the shared ones are read by the shells of every user,
+    /// whatever the umask of root is. Profiles in the home directory get the default one
+    fn mode(&self) -> Option<&'static str> {
+        self.system().then_some("0644")
+    }
+
+    /// path of the file in the home directory of the user, or of the SSH user
+    fn path(&self, user: Option<&str>) -> String {
+        let home = format!("~{}", user.unwrap_or(""));
+        match self {
+            ProfileTarget::Bash => format!("{}/.bashrc", home),
+            ProfileTarget::Zsh => format!("{}/.zshrc", home),
+            ProfileTarget::Fish => format!("{}/.config/fish/config.fish", home),
+            ProfileTarget::Environment => "/etc/environment".to_string(),
+            ProfileTarget::ProfileD => "/etc/profile.d/remote-playbook.sh".to_string(),
+        }
+    }
+
+    /// line of the profile that declares the entry, quoted for the shell
+    pub fn line(&self, kind: Entry, key: &str, value: &str) -> anyhow::Result<String> {
+        if key.is_empty() || key.contains(|c: char| c.is_whitespace() || "='\"$`\\".contains(c)) {
+            bail!("invalid name {:?}", key);
+        }
+        if value.contains('\n') {
+            bail!("{}: multiline values are not supported", key);
+        }
+        let line = match (self, kind) {
+            (ProfileTarget::Environment, Entry::Alias) => {
+                bail!("aliases cannot be declared in /etc/environment")
+            }
+            (ProfileTarget::Environment, Entry::Export) => {
+                // pam_env does not expand or unescape the value
+                if value.contains(['"', '\\']) {
+                    bail!("{}: quotes and backslashes are not supported", key);
+                }
+                format!("{}=\"{}\"", key, value)
+            }
+            (ProfileTarget::Fish, Entry::Alias) => format!("alias {} {}", key, fish_quote(value)),
+            (ProfileTarget::Fish, Entry::Export) => {
+                format!("set -gx {} {}", key, fish_quote(value))
+            }
+            (_, Entry::Alias) => format!("alias {}={}", key, sh_quote(value)),
+            (_, Entry::Export) => format!("export {}={}", key, sh_quote(value)),
+        };
+        Ok(line)
+    }
+
+    /// key of the entry, declared by the line of the profile
+    pub fn key<'a>(&self, kind: Entry, line: &'a str) -> Option<&'a str> {
+        let prefix = match (self, kind) {
+            (ProfileTarget::Environment, _) => "",
+            (ProfileTarget::Fish, Entry::Export) => "set -gx ",
+            (_, Entry::Alias) => "alias ",
+            (_, Entry::Export) => "export ",
+        };
+        let rest = line.trim().strip_prefix(prefix)?;
+        let key = rest.split(['=', ' ']).next()?;
+        if key.is_empty() || key.len() == rest.len() {
+            return None;
+        }
+        Some(key)
+    }
+}
+
+/// single-quoted for sh, bash and zsh: `'` is closed, escaped and reopened
+fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// single-quoted for fish, where `\` and `'` are escaped inside quotes
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// `aliases` and `exports` sections: either a table of entries for `~/.bashrc`,
+/// or a table with `target`, `user` and `items`
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "ProfileRepr")]
+pub struct ProfileOptions {
+    /// file to be edited, `bash` by default
+    pub target: ProfileTarget,
+    /// owner of the profile, SSH user by default. Edits are made with sudo
+    pub user: Option<String>,
+    /// declared entries, by name
+    pub items: Map<String, String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
+enum ProfileRepr {
+    /// entries of the shell profile, kept in a block owned by the playbook
+    Options {
+        /// file to be edited, `bash` by default
+        #[serde(default)]
+        target: ProfileTarget,
+        /// owner of the profile, SSH user by default. Edits are made with sudo
+        user: Option<String>,
+        /// declared entries, by name
+        items: Map<String, String>,
+    },
+    Items(Map<String, String>),
+}
+
+impl JsonSchema for ProfileOptions {
+    fn schema_name() -> String {
+        "ProfileOptions".to_string()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        ProfileRepr::json_schema(gen)
+    }
+}
+
+impl From<ProfileRepr> for ProfileOptions {
+    fn from(repr: ProfileRepr) -> Self {
+        match repr {
+            ProfileRepr::Options {
+                target,
+                user,
+                items,
+            } => ProfileOptions {
+                target,
+                user,
+                items,
+            },
+            ProfileRepr::Items(items) => ProfileOptions {
+                items,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl ProfileOptions {
+    fn path(&self) -> String {
+        self.target.path(self.user.as_deref())
+    }
+
+    fn sudo(&self) -> bool {
+        self.target.system() || self.user.is_some()
+    }
+
+    /// block of the stage in the profile. Top-level `[aliases]` and `[exports]`
+    /// keep the name of the section, blocks of other stages are named after both,
+    /// so stages editing the same file don't replace each other's block
+    fn block(&self, kind: Entry, stage: &str) -> anyhow::Result<BlockinfileOptions> {
+        if self.target.system() && self.user.is_some() {
+            bail!("user cannot be set for {}", self.path());
+        }
+        let lines = self
+            .items
+            .iter()
+            .map(|(key, value)| self.target.line(kind, key, value))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(BlockinfileOptions {
+            path: self.path(),
+            name: match stage == kind.section() {
+                true => stage.to_string(),
+                false => format!("{} {}", kind.section(), stage),
+            },
+            block: lines.join("\n"),
+            sudo: self.sudo(),
+            ..Default::default()
+        })
+    }
+
+    /// contents without the declared entries outside of the managed blocks.
+    /// Earlier versions appended `alias` and `export` lines to `~/.bashrc`
+    /// of the SSH user, their values are taken over by the block
+    fn migrate(&self, kind: Entry, contents: &str) -> String {
+        if self.target != ProfileTarget::Bash || self.user.is_some() {
+            return contents.to_string();
+        }
+        let mut inside = false;
+        let mut result = vec![];
+        for line in contents.lines() {
+            if line.starts_with("# BEGIN remote-playbook ") {
+                inside = true;
+            } else if line.starts_with("# END remote-playbook ") {
+                inside = false;
+            } else if !inside
+                && self
+                    .target
+                    .key(kind, line)
+                    .is_some_and(|key| self.items.contains_key(key))
+            {
+                continue;
+            }
+            result.push(line);
+        }
+        let mut result = result.join("\n");
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        result
+    }
+
+    /// entries of the block that is currently in the profile, by key
+    fn current_entries(
+        &self,
+        kind: Entry,
+        block: &BlockinfileOptions,
+        contents: &str,
+    ) -> Map<String, String> {
+        let (begin, end) = block.markers();
+        contents
+            .lines()
+            .skip_while(|x| x.trim_end() != begin)
+            .skip(1)
+            .take_while(|x| x.trim_end() != end)
+            .filter_map(|line| Some((self.target.key(kind, line)?.to_string(), line.to_string())))
+            .collect()
+    }
+}
+
+#[instrument(skip(client, ctx))]
+pub async fn on_install(
+    client: &Client,
+    ctx: &remote::Context,
+    kind: Entry,
+    stage: &str,
+    opt: &ProfileOptions,
+) -> anyhow::Result<()> {
+    let block = opt.block(kind, stage)?;
+    if let (Some(user), false) = (&opt.user, opt.target.system()) {
+        // created by the user, so that the copy made with sudo keeps the owner
+        let path = quote_path(&block.path);
+        let script = format!("mkdir -p \"$(dirname {})\" && touch {}", path, path);
+        let cmd = Cmd::new("sudo -u")
+            .arg(user)
+            .raw("sh -c")
+            .arg(script)
+            .raw("2>&1");
+        run(client, &cmd).await?;
+    }
+    let actual = read_text(client, &block.path, block.sudo).await?;
+    let contents = actual.as_deref().unwrap_or("");
+    let desired = block.apply(&opt.migrate(kind, contents))?;
+    if desired != contents {
+        // the whole file is rewritten, so it is confirmed on its own with `--step`
+        ctx.approve(&format!("rewrite {}", block.path))?;
+        replace_file(
+            client,
+            &block.path,
+            desired.as_bytes(),
+            block.sudo,
+            opt.target.mode(),
+            false,
+            None,
+        )
+        .await?;
+        info!("{} updated", block.path);
+    }
+    Ok(())
+}
+
+#[instrument(skip(client))]
+pub async fn on_check(
+    client: &Client,
+    kind: Entry,
+    stage: &str,
+    opt: &ProfileOptions,
+) -> anyhow::Result<Status> {
+    let mut success = vec![];
+    let mut fail = vec![];
+
+    let block = opt.block(kind, stage)?;
+    let actual = read_text(client, &block.path, block.sudo).await?;
+    let current = opt.current_entries(kind, &block, actual.as_deref().unwrap_or(""));
+    for (key, value) in &opt.items {
+        let line = opt.target.line(kind, key, value)?;
+        match current.get(key) {
+            Some(x) if *x == line => success.push(format!("{} ok", key)),
+            Some(x) => fail.push(format!("{} is `{}` instead of `{}`", key, x, line)),
+            None => fail.push(format!("{} missing", key)),
+        }
+    }
+    for key in current.keys() {
+        if !opt.items.contains_key(key) {
+            fail.push(format!("{} is not declared", key));
+        }
+    }
+
+    let contents = actual.as_deref().unwrap_or("");
+    let migrated = opt.migrate(kind, contents);
+    if migrated != contents {
+        fail.push(format!("{} declared outside of the block", kind.section()));
+    }
+    let desired = block.apply(&migrated)?;
+    let diff = unified_diff(&block.path, actual.as_deref(), &desired);
+    if !diff.is_empty() && fail.is_empty() {
+        fail.push(format!("{} block is not up to date", kind.section()));
+    }
+    Ok(Status::new(success, fail).with_diff(vec![diff]))
+}
+
+/// whether the block is managed by the `aliases` and `exports` sections
+fn is_managed(name: &str) -> bool {
+    [Entry::Alias, Entry::Export].iter().any(|kind| {
+        name == kind.section()
+            || name
+                .strip_prefix(kind.section())
+                .is_some_and(|x| x.starts_with(' '))
+    })
+}
+
+/// names of the managed blocks in the profile, that are not declared
+fn stale_blocks(contents: &str, declared: &[&str]) -> Vec<String> {
+    contents
+        .lines()
+        .filter_map(|line| line.trim_end().strip_prefix("# BEGIN remote-playbook "))
+        .filter(|name| is_managed(name) && !declared.contains(name))
+        .map(str::to_string)
+        .collect()
+}
+
+/// profile with managed blocks, that no stage of the playbook declares anymore
+struct Stale {
+    path: String,
+    sudo: bool,
+    names: Vec<String>,
+    actual: String,
+    desired: String,
+}
+
+/// blocks of removed sections and renamed stages. The declared profiles are looked up,
+/// and the default ones of the SSH user and of all users, that may have been declared before
+async fn stale(client: &Client, stages: &[(&str, &remote::Stage)]) -> anyhow::Result<Vec<Stale>> {
+    let mut declared = vec![];
+    for (name, stage) in stages {
+        if let Some(opt) = &stage.aliases {
+            declared.push(opt.block(Entry::Alias, name)?);
+        }
+        if let Some(opt) = &stage.exports {
+            declared.push(opt.block(Entry::Export, name)?);
+        }
+    }
+    // the files shared by all users are readable without sudo
+    let mut files = [
+        ProfileTarget::Bash,
+        ProfileTarget::Zsh,
+        ProfileTarget::Fish,
+        ProfileTarget::Environment,
+        ProfileTarget::ProfileD,
+    ]
+    .map(|x| (x.path(None), false, x.system()))
+    .to_vec();
+    for block in &declared {
+        if !files.iter().any(|(path, ..)| *path == block.path) {
+            files.push((block.path.clone(), block.sudo, block.sudo));
+        }
+    }
+
+    let mut result = vec![];
+    for (path, read_sudo, sudo) in files {
+        let names = declared
+            .iter()
+            .filter(|x| x.path == path)
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>();
+        let actual = match read_text(client, &path, read_sudo).await {
+            Ok(Some(actual)) => actual,
+            Ok(None) => continue,
+            // a file that was never declared may not be readable by the SSH user
+            Err(e) if names.is_empty() => {
+                warn!("{:#}", e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let names = stale_blocks(&actual, &names);
+        if names.is_empty() {
+            continue;
+        }
+        let mut desired = actual.clone();
+        for name in &names {
+            let block = BlockinfileOptions {
+                path: path.clone(),
+                name: name.clone(),
+                state: LineState::Absent,
+                ..Default::default()
+            };
+            desired = block.apply(&desired)?;
+        }
+        result.push(Stale {
+            path,
+            sudo,
+            names,
+            actual,
+            desired,
+        });
+    }
+    Ok(result)
+}
+
+/// remove the blocks of sections, that are no longer declared by the playbook.
+/// Returns the removed blocks
+#[instrument(skip_all)]
+pub async fn prune(
+    client: &Client,
+    ctx: &remote::Context,
+    stages: &[(&str, &remote::Stage)],
+) -> anyhow::Result<Vec<String>> {
+    let mut removed = vec![];
+    for file in stale(client, stages).await? {
+        let action = format!("remove {} from {}", file.names.join(", "), file.path);
+        if !matches!(ctx.confirm(&action)?, remote::Answer::Yes) {
+            continue;
+        }
+        replace_file(
+            client,
+            &file.path,
+            file.desired.as_bytes(),
+            file.sudo,
+            None,
+            false,
+            None,
+        )
+        .await?;
+        info!("{} updated", file.path);
+        for name in file.names {
+            removed.push(format!("{} in {}", name, file.path));
+        }
+    }
+    Ok(removed)
+}
+
+/// blocks of the profiles, that `prune` would remove
+#[instrument(skip_all)]
+pub async fn check_stale(
+    client: &Client,
+    stages: &[(&str, &remote::Stage)],
+) -> anyhow::Result<Status> {
+    let mut fail = vec![];
+    let mut diff = vec![];
+    for file in stale(client, stages).await? {
+        for name in &file.names {
+            fail.push(format!("{} in {} is not declared", name, file.path));
+        }
+        diff.push(unified_diff(&file.path, Some(&file.actual), &file.desired));
+    }
+    Ok(Status::new(vec![], fail).with_diff(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_entries_of_the_block() {
+        let bashrc = "alias ll='ls -alF'\n\
+            # BEGIN remote-playbook aliases\n\
+            alias k='kubectl'\n\
+            alias tf='terraform'\n\
+            # END remote-playbook aliases\n";
+        let opt = ProfileOptions::default();
+        let aliases = opt.block(Entry::Alias, "aliases").unwrap();
+        let current = opt.current_entries(Entry::Alias, &aliases, bashrc);
+        assert_eq!(current.len(), 2);
+        assert_eq!(current["k"], "alias k='kubectl'");
+        let exports = opt.block(Entry::Export, "exports").unwrap();
+        assert!(opt
+            .current_entries(Entry::Export, &exports, bashrc)
+            .is_empty());
+    }
+
+    #[test]
+    fn it_migrates_entries_outside_of_the_block() {
+        let bashrc = "alias ll='ls -alF'\n\
+            alias k='kubectl'\n\
+            # BEGIN remote-playbook aliases\n\
+            alias tf='terraform'\n\
+            # END remote-playbook aliases\n";
+        let mut opt = ProfileOptions::default();
+        opt.items.insert("k".to_string(), "kubectl".to_string());
+        opt.items.insert("tf".to_string(), "terraform".to_string());
+        let migrated = opt.migrate(Entry::Alias, bashrc);
+        assert_eq!(
+            migrated,
+            "alias ll='ls -alF'\n\
+            # BEGIN remote-playbook aliases\n\
+            alias tf='terraform'\n\
+            # END remote-playbook aliases\n"
+        );
+        let desired = opt
+            .block(Entry::Alias, "aliases")
+            .unwrap()
+            .apply(&migrated)
+            .unwrap();
+        assert!(desired.contains(
+            "# BEGIN remote-playbook aliases\nalias k='kubectl'\nalias tf='terraform'\n"
+        ));
+        assert_eq!(opt.migrate(Entry::Alias, &desired), desired);
+    }
+
+    #[test]
+    fn it_keeps_the_blocks_of_every_stage() {
+        let mut shell = ProfileOptions::default();
+        shell.items.insert("k".to_string(), "kubectl".to_string());
+        let mut tools = ProfileOptions::default();
+        tools
+            .items
+            .insert("tf".to_string(), "terraform".to_string());
+        let shell = shell.block(Entry::Alias, "shell").unwrap();
+        let tools_block = tools.block(Entry::Alias, "tools").unwrap();
+        let bashrc = tools_block.apply(&shell.apply("").unwrap()).unwrap();
+        assert_eq!(
+            bashrc,
+            "# BEGIN remote-playbook aliases shell\n\
+            alias k='kubectl'\n\
+            # END remote-playbook aliases shell\n\
+            # BEGIN remote-playbook aliases tools\n\
+            alias tf='terraform'\n\
+            # END remote-playbook aliases tools\n"
+        );
+        assert_eq!(shell.apply(&bashrc).unwrap(), bashrc);
+        let current = tools.current_entries(Entry::Alias, &tools_block, &bashrc);
+        assert_eq!(current.keys().collect::<Vec<_>>(), vec!["tf"]);
+    }
+
+    #[test]
+    fn it_finds_blocks_that_are_not_declared() {
+        let bashrc = "# BEGIN remote-playbook aliases\n\
+            # END remote-playbook aliases\n\
+            # BEGIN remote-playbook aliases k8s\n\
+            alias k='kubectl'\n\
+            # END remote-playbook aliases k8s\n\
+            # BEGIN remote-playbook exports\n\
+            # END remote-playbook exports\n\
+            # BEGIN remote-playbook aliasesx\n\
+            # END remote-playbook aliasesx\n\
+            # BEGIN remote-playbook proxy\n\
+            # END remote-playbook proxy\n";
+        assert_eq!(
+            stale_blocks(bashrc, &["aliases", "aliases kubernetes"]),
+            vec!["aliases k8s", "exports"]
+        );
+        assert!(stale_blocks(bashrc, &["aliases", "aliases k8s", "exports"]).is_empty());
+    }
+
+    #[test]
+    fn it_creates_shared_profiles_readable_by_all_users() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("remote-playbook-profile-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let tmp = dir.join("tmp").display().to_string();
+        let dest = dir.join("profile.d/remote-playbook.sh");
+        std::fs::write(&tmp, "export A='1'\n").unwrap();
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let cmd = place(
+            &tmp,
+            &dest.display().to_string(),
+            false,
+            ProfileTarget::ProfileD.mode(),
+        );
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("umask 077; {}", cmd))
+            .status()
+            .unwrap();
+        assert!(status.success(), "{}", cmd);
+        let mode = std::fs::metadata(&dest).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o644);
+        assert_eq!(ProfileTarget::Bash.mode(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn it_quotes_values_per_shell() {
+        let value = r"echo 'it\'s'";
+        let line = ProfileTarget::Bash.line(Entry::Alias, "hi", value).unwrap();
+        assert_eq!(line, r"alias hi='echo '\''it\'\''s'\'''");
+        let line = ProfileTarget::Fish.line(Entry::Alias, "hi", value).unwrap();
+        assert_eq!(line, r"alias hi 'echo \'it\\\'s\''");
+        assert_eq!(ProfileTarget::Fish.key(Entry::Alias, &line), Some("hi"));
+        let line = ProfileTarget::Environment
+            .line(Entry::Export, "EDITOR", "vim")
+            .unwrap();
+        assert_eq!(line, "EDITOR=\"vim\"");
+        assert!(ProfileTarget::Environment
+            .line(Entry::Alias, "k", "kubectl")
+            .is_err());
+    }
+}
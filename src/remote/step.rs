@@ -0,0 +1,74 @@
+//! confirmations of `install --step`, asked before every module
+//! and before destructive commands inside the modules
+use color_eyre::owo_colors::OwoColorize;
+use std::io::Write;
+
+/// answer of the operator to a step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Answer {
+    /// run the module or the command
+    Yes,
+    /// leave the module out and go on with the next one
+    No,
+    /// leave out the rest of the stage
+    Skip,
+    /// stop the run on the host
+    Abort,
+}
+
+impl std::str::FromStr for Answer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "y" | "yes" => Ok(Answer::Yes),
+            "n" | "no" => Ok(Answer::No),
+            "s" | "skip" => Ok(Answer::Skip),
+            "a" | "abort" => Ok(Answer::Abort),
+            other => anyhow::bail!("unknown answer {:?}", other),
+        }
+    }
+}
+
+/// a destructive command of the module was declined, the module is left out
+/// as if it was declined before it started
+#[derive(Debug)]
+pub struct Declined(pub Answer);
+
+impl std::fmt::Display for Declined {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "declined ({:?})", self.0)
+    }
+}
+
+impl std::error::Error for Declined {}
+
+/// ask until the answer is known, the end of the input aborts
+pub fn ask(question: &str) -> anyhow::Result<Answer> {
+    loop {
+        print!("? {} [y]es/[n]o/[s]kip stage/[a]bort: ", question.cyan());
+        std::io::stdout().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            println!();
+            return Ok(Answer::Abort);
+        }
+        if let Ok(answer) = line.parse() {
+            return Ok(answer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_parses_answers() {
+        assert_eq!("y\n".parse::<Answer>().unwrap(), Answer::Yes);
+        assert_eq!("No".parse::<Answer>().unwrap(), Answer::No);
+        assert_eq!(" s ".parse::<Answer>().unwrap(), Answer::Skip);
+        assert_eq!("abort".parse::<Answer>().unwrap(), Answer::Abort);
+        assert!("maybe".parse::<Answer>().is_err());
+    }
+}
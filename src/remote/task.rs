@@ -0,0 +1,217 @@
+//! settings, that are common to all modules of a stage. They are declared
+//! in the table of the module next to its options, i.e. `mount = { to = "/data", on_error = "continue" }`
+use crate::expr::Expr;
+use crate::prelude::*;
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{Schema, SingleOrVec};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// what happens with the run, when a module fails
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// stop the run on this host
+    Abort,
+    /// go on with the next module
+    Continue,
+    /// go on with the next stage
+    SkipStage,
+}
+
+impl OnError {
+    /// policy, when it is not declared for the module, its stage or the playbook:
+    /// modules that the next stages depend on, abort the run
+    pub fn default_for(module: &str) -> Self {
+        match module {
+            "mount" => OnError::Abort,
+            _ => OnError::Continue,
+        }
+    }
+}
+
+/// options of the module with the common settings
+#[derive(Debug, Default)]
+pub struct Task<T> {
+    pub on_error: Option<OnError>,
+    /// the module is applied only when the expression holds
+    pub when: Option<Expr>,
+    /// tags to select the module with `--tags`, in addition to the tags of the stage
+    pub tags: Vec<String>,
+    pub opt: T,
+}
+
+impl<T> From<T> for Task<T> {
+    fn from(opt: T) -> Self {
+        Task {
+            on_error: None,
+            when: None,
+            tags: vec![],
+            opt,
+        }
+    }
+}
+
+impl<T> std::ops::Deref for Task<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.opt
+    }
+}
+
+/// common setting is taken out of the table of the module,
+/// or out of the tables, if the module is an array of tables.
+/// It applies to the whole module, so the tables must not declare different values
+fn take<V: DeserializeOwned>(value: &mut Value, key: &str) -> Result<Option<V>, String> {
+    let found = match value {
+        Value::Object(map) => map.remove(key),
+        Value::Array(items) => {
+            let mut found = items
+                .iter_mut()
+                .filter_map(|item| item.as_object_mut()?.remove(key))
+                .collect::<Vec<_>>();
+            if found.windows(2).any(|x| x[0] != x[1]) {
+                return Err(format!(
+                    "{} differs between the tables of the module, \
+                    it applies to all of them and is declared once",
+                    key
+                ));
+            }
+            found.pop()
+        }
+        _ => None,
+    };
+    found
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|e| e.to_string())
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Task<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let mut value = Value::deserialize(deserializer)?;
+        let on_error = take(&mut value, "on_error").map_err(D::Error::custom)?;
+        let when = take(&mut value, "when").map_err(D::Error::custom)?;
+        let tags = take(&mut value, "tags")
+            .map_err(D::Error::custom)?
+            .unwrap_or_default();
+        let opt = T::deserialize(value).map_err(D::Error::custom)?;
+        Ok(Task {
+            on_error,
+            when,
+            tags,
+            opt,
+        })
+    }
+}
+
+/// common settings are added to the tables of the module in the schema
+fn with_common(schema: &mut Schema, gen: &mut SchemaGenerator) {
+    let Schema::Object(schema) = schema else {
+        return;
+    };
+    if let Some(any_of) = schema.subschemas.as_mut().and_then(|x| x.any_of.as_mut()) {
+        for schema in any_of {
+            with_common(schema, gen);
+        }
+    }
+    if let Some(SingleOrVec::Single(items)) = schema.array.as_mut().and_then(|x| x.items.as_mut()) {
+        with_common(items, gen);
+    }
+    if let Some(object) = schema.object.as_mut() {
+        let properties = [
+            ("on_error", gen.subschema_for::<OnError>()),
+            ("when", gen.subschema_for::<Expr>()),
+            ("tags", gen.subschema_for::<Vec<String>>()),
+        ];
+        for (name, property) in properties {
+            object.properties.insert(name.to_string(), property);
+        }
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Task<T> {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        T::schema_name()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema = T::json_schema(gen);
+        with_common(&mut schema, gen);
+        schema
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{CopyOptions, MountOptions};
+
+    #[test]
+    fn it_takes_common_settings() {
+        let task: Task<MountOptions> = toml::from_str(
+            "to = \"/data\"\non_error = \"skip_stage\"\nwhen = \"facts.arch == 'x86_64'\"",
+        )
+        .unwrap();
+        assert_eq!(task.on_error, Some(OnError::SkipStage));
+        assert_eq!(
+            task.when.as_ref().map(|x| x.src()),
+            Some("facts.arch == 'x86_64'")
+        );
+        assert_eq!(task.to, "/data");
+
+        #[derive(Deserialize)]
+        struct Stage {
+            copy: Task<OneOrMany<CopyOptions>>,
+        }
+        let stage: Stage = toml::from_str(
+            "copy = [{ src = \"a\", dest = \"/a\" }, { src = \"b\", dest = \"/b\", on_error = \"abort\" }]",
+        )
+        .unwrap();
+        assert_eq!(stage.copy.on_error, Some(OnError::Abort));
+        assert_eq!(stage.copy.len(), 2);
+
+        let stage: Stage = toml::from_str(
+            "copy = [{ src = \"a\", dest = \"/a\", tags = [\"x\"] }, { src = \"b\", dest = \"/b\", tags = [\"x\"] }]",
+        )
+        .unwrap();
+        assert_eq!(stage.copy.tags, vec!["x"]);
+    }
+
+    #[test]
+    fn it_refuses_settings_that_differ_between_tables() {
+        #[derive(Debug, Deserialize)]
+        struct Stage {
+            #[allow(dead_code)]
+            copy: Task<OneOrMany<CopyOptions>>,
+        }
+        let settings = [
+            ("on_error", "\"abort\"", "\"continue\""),
+            (
+                "when",
+                "\"facts.arch == 'x86_64'\"",
+                "\"facts.arch == 'aarch64'\"",
+            ),
+            ("tags", "[\"x\"]", "[\"y\"]"),
+        ];
+        for (key, a, b) in settings {
+            let playbook = format!(
+                "copy = [{{ src = \"a\", dest = \"/a\", {key} = {a} }}, {{ src = \"b\", dest = \"/b\", {key} = {b} }}]",
+            );
+            let err = toml::from_str::<Stage>(&playbook).unwrap_err();
+            assert!(
+                err.to_string()
+                    .contains(&format!("{} differs between the tables", key)),
+                "{}",
+                err
+            );
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::prelude::*;
+use crate::remote;
+use minijinja::{Environment, UndefinedBehavior};
+
+/// render a local template with the variables of the host and upload it
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TemplateOptions {
+    /// local template file, rendered with the variables of the host
+    pub src: String,
+    /// remote destination file
+    pub dest: String,
+    /// permissions of the rendered file, i.e. `0644`
+    pub mode: Option<String>,
+    /// owner of the rendered file, i.e. `root` or `www-data`
+    pub owner: Option<String>,
+    /// write the file with sudo, enabled by default
+    #[serde(
+        default = "bool_true",
+        deserialize_with = "deserialize_bool_from_anything"
+    )]
+    pub sudo: bool,
+}
+
+/// render the template locally with the variables of the host.
+/// Besides the playbook variables, `host` contains the name of the host
+pub fn render(opt: &TemplateOptions, ctx: &remote::Context) -> anyhow::Result<String> {
+    let local_path = crate::connect::tilde_with_context(&opt.src, dirs::home_dir);
+    let source =
+        std::fs::read_to_string(&local_path).context(format!("read template {}", opt.src))?;
+
+    let mut env = Environment::new();
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_keep_trailing_newline(true);
+    let template = env.template_from_named_str(&opt.src, &source)?;
+
+    let mut vars = ctx.vars.clone();
+    vars.insert("host".to_string(), ctx.host.clone().into());
+    Ok(template.render(&vars)?)
+}
+
+#[instrument(skip(client, ctx))]
+pub async fn on_install(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &[TemplateOptions],
+) -> anyhow::Result<()> {
+    for item in opt {
+        let rendered = render(item, ctx)?;
+        let remote = remote_checksums(client, &item.dest, item.sudo).await?;
+        if remote.get(&item.dest) == Some(&sha256_hex(rendered.as_bytes())) {
+            debug!("{} is up to date", item.dest);
+        } else {
+            upload(
+                client,
+                rendered.as_bytes(),
+                &item.dest,
+                item.sudo,
+                item.mode.as_deref(),
+            )
+            .await?;
+            info!("{} rendered", item.dest);
+        }
+        if let Some(owner) = &item.owner {
+            let cmd = Cmd::sudo(item.sudo, "chown")
+                .operands()
+                .arg(owner)
+                .path(&item.dest)
+                .raw("2>&1");
+            run(client, &cmd).await?;
+        }
+        if let Some(mode) = &item.mode {
+            let cmd = Cmd::sudo(item.sudo, "chmod")
+                .operands()
+                .arg(mode)
+                .path(&item.dest)
+                .raw("2>&1");
+            run(client, &cmd).await?;
+        }
+    }
+    Ok(())
+}
+
+#[instrument(skip(client, ctx))]
+pub async fn on_check(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &[TemplateOptions],
+) -> anyhow::Result<Status> {
+    let mut success = vec![];
+    let mut fail = vec![];
+    let mut diff = vec![];
+    for item in opt {
+        let rendered = render(item, ctx)?;
+        let actual = read_file(client, &item.dest, item.sudo).await?;
+        let text = file_diff(&item.dest, actual.as_deref(), rendered.as_bytes());
+        if text.is_empty() {
+            success.push(format!("{} ok", item.dest));
+        } else {
+            match actual {
+                Some(_) => fail.push(format!("{} changed", item.dest)),
+                None => fail.push(format!("{} missing", item.dest)),
+            }
+            diff.push(text);
+        }
+    }
+    Ok(Status::new(success, fail).with_diff(diff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_renders_loops_and_conditions() {
+        let src = std::env::temp_dir().join("remote-playbook-test.env.j2");
+        std::fs::write(
+            &src,
+            "{% for w in workers %}WORKER_{{ loop.index }}={{ w }}\n{% endfor %}\
+             {% if debug %}DEBUG=1\n{% endif %}HOST={{ host }}\n",
+        )
+        .unwrap();
+        let opt = TemplateOptions {
+            src: src.to_string_lossy().to_string(),
+            dest: "/etc/app.env".to_string(),
+            ..Default::default()
+        };
+        let mut ctx = remote::Context {
+            host: "web1".to_string(),
+            ..Default::default()
+        };
+        ctx.vars
+            .insert("workers".to_string(), serde_json::json!(["a", "b"]));
+        ctx.vars.insert("debug".to_string(), false.into());
+
+        let rendered = render(&opt, &ctx).unwrap();
+        assert_eq!(rendered, "WORKER_1=a\nWORKER_2=b\nHOST=web1\n");
+
+        ctx.vars.remove("workers");
+        assert!(render(&opt, &ctx).is_err());
+    }
+}
@@ -1,6 +1,9 @@
 use crate::prelude::*;
+use crate::remote;
 
-#[derive(Debug, Default, Deserialize)]
+/// install terraform from HashiCorp apt repository
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct TerraformOptions {}
 
 const GPG_PATH: &str = "/usr/share/keyrings/hashicorp-archive-keyring.gpg";
@@ -25,14 +28,18 @@ pub async fn on_install(client: &Client, opt: &TerraformOptions) -> anyhow::Resu
     run(client, &cmd).await?;
 
     if !file_exists(client, SOURCES_LIST_PATH).await {
-        let lsb_release = run(&client, "lsb_release -cs").await?.output.trim().lines().next().unwrap_or("").to_string();
+        let lsb_release = run(client, "lsb_release -cs").await?.output.trim().lines().next().unwrap_or("").to_string();
         // setup apt repo
-        let cmd = format!(
-            "echo \"deb [signed-by={}] https://apt.releases.hashicorp.com {} main\" | sudo tee {} > /dev/null",
+        let line = format!(
+            "deb [signed-by={}] https://apt.releases.hashicorp.com {} main",
             GPG_PATH,
             lsb_release, 
-            SOURCES_LIST_PATH,
         );
+        let cmd = Cmd::new("echo")
+            .arg(line)
+            .raw("| sudo tee")
+            .path(SOURCES_LIST_PATH)
+            .raw("> /dev/null");
         run(client, &cmd).await?;
     }
     run(client, "export DEBIAN_FRONTEND=noninteractive").await?;
@@ -51,24 +58,34 @@ pub async fn on_install(client: &Client, opt: &TerraformOptions) -> anyhow::Resu
     Ok(())
 }
 
-#[instrument(skip(client))]
-pub async fn on_check(client: &Client, opt: &TerraformOptions) -> anyhow::Result<Status> {
+#[instrument(skip(client, ctx))]
+pub async fn on_check(
+    client: &Client,
+    ctx: &remote::Context,
+    opt: &TerraformOptions,
+) -> anyhow::Result<Status> {
     let mut success = vec![];
     let mut fail = vec![];
 
-    match which(client, "terraform --version 2>&1").await {
+    let cmds = [
+        Cmd::new("terraform --version 2>&1"),
+        Cmd::new("ls -1").path(GPG_PATH),
+        Cmd::new("ls -1").path(SOURCES_LIST_PATH),
+    ];
+    let out = probe(client, ctx.probing, &cmds).await?;
+    match found(&out[0]) {
         Ok(msg) => {
             let first_line = msg.lines().next().unwrap_or("").to_string();
             success.push(first_line);
         }
         Err(res) => fail.push(res.to_string()),
     };
-    if file_exists(client, GPG_PATH).await {
+    if out[1].exit_status == 0 {
         success.push("gpg key ok".to_string());
     } else {
         fail.push("missing gpg key".to_string());
     }
-    if file_exists(client, SOURCES_LIST_PATH).await {
+    if out[2].exit_status == 0 {
         success.push("sources list ok".to_string());
     } else {
         fail.push(format!("missing {}", SOURCES_LIST_PATH));
@@ -0,0 +1,164 @@
+//! state of the host: a ledger of the runs of `install`, kept on the host itself,
+//! so it is known which playbook revision built the server, when and by whom
+use crate::prelude::*;
+use crate::remote::{OnError, Stage, StageReport};
+use chrono::{DateTime, Utc};
+
+const STATE_PATH: &str = "/var/lib/remote-playbook/state.json";
+/// older runs are dropped from the ledger
+const MAX_RUNS: usize = 100;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    pub runs: Vec<Run>,
+}
+
+/// single run of `install` on the host
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Run {
+    pub started: DateTime<Utc>,
+    pub finished: DateTime<Utc>,
+    /// `user@host` who ran the playbook
+    pub operator: String,
+    /// path of the playbook, as it was given
+    pub playbook: String,
+    pub playbook_sha256: String,
+    pub stages: Vec<StageReport>,
+    /// versions of the packages and tools installed by the stages
+    #[serde(default)]
+    pub versions: Map<String, String>,
+}
+
+impl Run {
+    pub fn ok(&self) -> bool {
+        self.stages
+            .iter()
+            .flat_map(|stage| &stage.modules)
+            .all(|module| module.error.is_none())
+    }
+}
+
+/// ledger of the host, empty only if nothing was recorded yet.
+/// A ledger that can't be read or parsed is an error, so `record` never replaces it
+pub async fn read(client: &Client) -> anyhow::Result<State> {
+    parse(read_file(client, STATE_PATH, true).await?.as_deref())
+}
+
+fn parse(contents: Option<&[u8]>) -> anyhow::Result<State> {
+    match contents {
+        Some(contents) => serde_json::from_slice(contents).context(format!("parse {}", STATE_PATH)),
+        None => Ok(State::default()),
+    }
+}
+
+/// append the run to the ledger of the host
+#[instrument(skip(client, run))]
+pub async fn record(client: &Client, run: Run) -> anyhow::Result<()> {
+    let mut state = read(client).await?;
+    state.runs.push(run);
+    if state.runs.len() > MAX_RUNS {
+        state.runs.drain(..state.runs.len() - MAX_RUNS);
+    }
+    let contents = serde_json::to_string_pretty(&state)?;
+    upload(client, contents.as_bytes(), STATE_PATH, true, None).await
+}
+
+/// versions of the apt packages and of the tools, that are installed by the stages
+#[instrument(skip(client, stages))]
+pub async fn versions(
+    client: &Client,
+    probing: Probing,
+    stages: &[(&str, &Stage)],
+) -> anyhow::Result<Map<String, String>> {
+    let mut names = vec![];
+    let mut cmds = vec![];
+    for (_, stage) in stages {
+        for package in stage.apt.iter().flat_map(|apt| &apt.install) {
+            names.push(package.clone());
+            cmds.push(
+                Cmd::new("dpkg-query -W -f='${Version}'")
+                    .operands()
+                    .arg(package),
+            );
+        }
+        let tools = [
+            ("docker", stage.docker.is_some(), "docker --version"),
+            ("terraform", stage.terraform.is_some(), "terraform version"),
+            ("aws", stage.aws.is_some(), "aws --version"),
+        ];
+        for (name, used, cmd) in tools {
+            if used {
+                names.push(name.to_string());
+                cmds.push(Cmd::new(cmd).raw("2>&1"));
+            }
+        }
+    }
+    let out = probe(client, probing, &cmds).await?;
+    Ok(names
+        .into_iter()
+        .zip(out)
+        .filter(|(_, out)| out.exit_status == 0)
+        .map(|(name, out)| {
+            let version = out.output.lines().next().unwrap_or("").trim().to_string();
+            (name, version)
+        })
+        .collect())
+}
+
+/// print the latest runs recorded on the host
+pub fn print(state: &State, limit: usize) {
+    if state.runs.is_empty() {
+        println!("  no runs recorded");
+    }
+    let skip = state.runs.len().saturating_sub(limit);
+    for run in state.runs.iter().skip(skip) {
+        let header = format!(
+            "{} {} {} {}",
+            run.started.format("%Y-%m-%d %H:%M:%S"),
+            run.operator,
+            run.playbook,
+            &run.playbook_sha256[..12.min(run.playbook_sha256.len())]
+        );
+        if run.ok() {
+            println!("+ {}", header.green());
+        } else {
+            println!("- {}", header.red());
+        }
+        for stage in &run.stages {
+            let modules = stage
+                .modules
+                .iter()
+                .map(|module| match &module.error {
+                    None => module.module.clone(),
+                    Some(e) => format!("{} ({})", module.module, e).red().to_string(),
+                })
+                .collect::<Vec<_>>();
+            let stopped = match stage.stopped {
+                Some(OnError::Abort) => " (aborted)",
+                Some(OnError::SkipStage) => " (skipped the rest)",
+                _ => "",
+            };
+            println!(
+                "  {}: {}{}",
+                stage.name.yellow(),
+                modules.join(", "),
+                stopped.red()
+            );
+        }
+        for (name, version) in &run.versions {
+            println!("  {} {}", name.cyan(), version);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_starts_a_ledger_only_when_it_is_missing() {
+        assert!(parse(None).unwrap().runs.is_empty());
+        assert!(parse(Some(br#"{"runs":[]}"#)).unwrap().runs.is_empty());
+        assert!(parse(Some(b"{\"runs\":[")).is_err());
+    }
+}